@@ -0,0 +1,236 @@
+//! Static analysis of a config file done by `rkd check`.
+use super::*;
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub errors: Vec<ConfigError>,
+    pub warnings: Vec<String>,
+}
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+// Parses the config and looks for keybindings that can never be triggered or
+// that will be triggered along with other keybindings.
+pub fn check<P: AsRef<Path>>(cfg: &Cfg<P>) -> Report {
+    match cfg.bindings() {
+        Ok(bindings) => check_bindings(cfg.file(), &bindings),
+        Err(errors) => Report {
+            errors,
+            ..Default::default()
+        },
+    }
+}
+
+pub fn check_bindings(file: &Path, bindings: &[Binding]) -> Report {
+    let mut report = Report::default();
+    for binding in bindings {
+        let chords = binding.chain.chords();
+        // the parser already rejects unknown keys, this catches bindings built
+        // elsewhere, like `Key::Code` of a code that has a name
+        if chords.iter().any(|chord| !chord.key.is_reachable()) {
+            report.errors.push(
+                ConfigError::new(
+                    ConfigErrorKind::UnreachableKeybinding,
+                    binding.chain.to_string(),
+                )
+                .location(file, binding.line, &binding.source),
+            );
+        }
+        for other in bindings.iter().filter(|other| overlaps(binding, other)) {
+            // common in sxhkd configs, `super + {_,shift + }{1-9}`, so only a warning
+            if is_prefix(&binding.chain, &other.chain) {
+                let key = chords[chords.len() - 1].key;
+                report.warnings.push(format!(
+                    "warning: keybinding '{}' at line {} is a prefix of '{}' at line {} - pressing the remaining modifiers while holding '{}' triggers both",
+                    binding.chain, binding.line, other.chain, other.line, key
+                ));
+            }
+            if binding.chain.continues(&other.chain) {
+                report.errors.push(
+                    ConfigError::new(
+                        ConfigErrorKind::ShadowedKeybinding(other.chain.to_string(), other.line),
+                        binding.chain.to_string(),
+                    )
+                    .location(file, binding.line, &binding.source),
+                );
+            }
+        }
+    }
+    for mode in inescapable_modes(bindings) {
+        report.warnings.push(format!(
+            "warning: no keybinding of mode '{}' enters another mode - it can't be left",
+            mode
+        ));
+    }
+    report
+}
+
+// Modes other than the default one without a keybinding switching to another mode
+fn inescapable_modes(bindings: &[Binding]) -> Vec<&str> {
+    let mut modes: Vec<&str> = bindings
+        .iter()
+        .map(|b| b.mode.as_str())
+        .filter(|mode| *mode != DEFAULT_MODE)
+        .collect();
+    modes.sort_unstable();
+    modes.dedup();
+    modes.retain(|mode| {
+        !bindings
+            .iter()
+            .any(|b| b.mode == *mode && b.enter.as_ref().is_some_and(|enter| enter != mode))
+    });
+    modes
+}
+
+// Whether both keybindings can be active on the same keyboard at once. Keybindings
+// of different device sections are assumed to apply to different keyboards.
+fn overlaps(binding: &Binding, other: &Binding) -> bool {
+    binding.mode == other.mode
+        && (binding.device.is_none() || other.device.is_none() || binding.device == other.device)
+}
+
+// A chain is a prefix of another if both have the same chords except for the last
+// ones, which share the trigger key while the modifiers of the first one are a
+// strict subset of the second one's.
+fn is_prefix(chain: &Chain, other: &Chain) -> bool {
+    let (chords, other_chords) = (chain.chords(), other.chords());
+    let n = chords.len();
+    n == other_chords.len()
+        && chords[..n - 1] == other_chords[..n - 1]
+        && is_chord_prefix(&chords[n - 1], &other_chords[n - 1])
+}
+
+fn is_chord_prefix(chord: &Chord, other: &Chord) -> bool {
+    chord.key == other.key
+        && chord.release == other.release
+        && other
+            .modifiers
+            .variants()
+            .into_iter()
+            .any(|m| m != chord.modifiers && m.contains(chord.modifiers))
+}
+
+#[cfg(test)]
+mod tests {
+    use self::Key::*;
+    use super::*;
+
+    fn binding(keys: &[Key], line: usize) -> Binding {
+        Binding {
+            chain: Chord::from_keys(keys).unwrap().into(),
+            exec: Some(Exec::new("true").unwrap()),
+            repeat: Repeat::Never,
+            mode: DEFAULT_MODE.to_string(),
+            device: None,
+            enter: None,
+            line,
+            source: String::new(),
+        }
+    }
+
+    #[test]
+    fn finds_problems() {
+        let bindings = vec![
+            binding(&[Super, Q], 1),
+            binding(&[Super, Shift, Q], 3),
+            binding(&[Super, Ctrl, Shift, Q], 5),
+            binding(&[Super, W], 7),
+            binding(&[Ctrl, W], 9),
+            binding(&[Super, UK], 11),
+            // q has a name, so its code never produces Key::Code
+            binding(&[Super, Code(16)], 13),
+        ];
+        let report = check_bindings(Path::new("rkdrc"), &bindings);
+        assert!(!report.is_ok());
+        let lines: Vec<_> = report.errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, vec![11, 13]);
+        assert!(report
+            .errors
+            .iter()
+            .all(|e| matches!(e.kind, ConfigErrorKind::UnreachableKeybinding)));
+        assert_eq!(report.warnings.len(), 3);
+        assert!(report.warnings[0].contains("'super + q' at line 1"));
+        assert!(report.warnings[0].contains("'super + shift + q' at line 3"));
+        assert!(report.warnings[2].contains("'super + shift + q' at line 3"));
+        assert!(report.warnings[2].contains("'super + ctrl + shift + q' at line 5"));
+    }
+
+    #[test]
+    fn finds_shadowed_chains() {
+        let mut chain = binding(&[Super, W], 3);
+        chain.chain = Chain::new(vec![
+            Chord::from_keys(&[Super, W]).unwrap(),
+            Chord::from_keys(&[H]).unwrap(),
+        ]);
+        let bindings = vec![binding(&[Super, W], 1), chain, binding(&[Super, H], 5)];
+        let report = check_bindings(Path::new("rkdrc"), &bindings);
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].line, 3);
+        assert!(matches!(
+            report.errors[0].kind,
+            ConfigErrorKind::ShadowedKeybinding(ref other, 1) if other == "super + w"
+        ));
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn checks_modes() {
+        let mut resize = binding(&[Super, Shift, Q], 5);
+        resize.mode = "resize".to_string();
+        let mut launcher = binding(&[Super, Q], 9);
+        launcher.mode = "launcher".to_string();
+        launcher.enter = Some(DEFAULT_MODE.to_string());
+        let bindings = vec![binding(&[Super, Q], 1), resize, launcher];
+        let report = check_bindings(Path::new("rkdrc"), &bindings);
+        assert!(report.is_ok());
+        // keybindings of different modes don't interfere
+        assert_eq!(report.warnings.len(), 1);
+        assert!(report.warnings[0].contains("mode 'resize'"));
+    }
+
+    #[test]
+    fn checks_device_sections() {
+        let pad = DeviceFilter::parse("name=Pad").unwrap();
+        let other_pad = DeviceFilter::parse("name=\"Other Pad\"").unwrap();
+        let scoped = |keys: &[Key], line, filter: &DeviceFilter| {
+            let mut b = binding(keys, line);
+            b.device = Some(filter.clone());
+            b
+        };
+        let bindings = vec![
+            binding(&[Super, Q], 1),
+            scoped(&[Super, Shift, Q], 3, &pad),
+            scoped(&[Super, W], 5, &pad),
+            scoped(&[Super, Shift, W], 7, &other_pad),
+            // overrides the global keybinding on the pad
+            scoped(&[Super, Q], 9, &pad),
+        ];
+        let report = check_bindings(Path::new("rkdrc"), &bindings);
+        assert!(report.is_ok());
+        assert_eq!(report.warnings.len(), 2);
+        assert!(report.warnings[0].contains("'super + q' at line 1"));
+        assert!(report.warnings[1].contains("'super + q' at line 9"));
+    }
+
+    #[test]
+    fn accepts_valid_bindings() {
+        let bindings = vec![
+            binding(&[Super, Q], 1),
+            binding(&[Super, W], 3),
+            binding(&[XF86AudioPlay], 5),
+        ];
+        let report = check_bindings(Path::new("rkdrc"), &bindings);
+        assert!(report.is_ok());
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn accepts_example_config() {
+        let cfg = Cfg::new(Path::new(env!("CARGO_MANIFEST_DIR")).join("example_conf"));
+        let report = check(&cfg);
+        assert!(report.is_ok(), "{:?}", report.errors);
+    }
+}
@@ -0,0 +1,429 @@
+use super::*;
+use std::fmt;
+use std::ops::BitOr;
+
+/// Bitmask of modifier keys that have to be held for a chord to match.
+///
+/// Pressed keys always set the bit of their side, `SHIFT`, `CTRL`, `ALT` and `SUPER`
+/// stand for either side and only appear in keybindings.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Modifiers(u16);
+impl Modifiers {
+    pub const NONE: Modifiers = Modifiers(0);
+    pub const SHIFT: Modifiers = Modifiers(1);
+    pub const CTRL: Modifiers = Modifiers(1 << 1);
+    pub const ALT: Modifiers = Modifiers(1 << 2);
+    pub const SUPER: Modifiers = Modifiers(1 << 3);
+    pub const LSHIFT: Modifiers = Modifiers(1 << 4);
+    pub const RSHIFT: Modifiers = Modifiers(1 << 5);
+    pub const LCTRL: Modifiers = Modifiers(1 << 6);
+    pub const RCTRL: Modifiers = Modifiers(1 << 7);
+    pub const LALT: Modifiers = Modifiers(1 << 8);
+    pub const RALT: Modifiers = Modifiers(1 << 9);
+    pub const LSUPER: Modifiers = Modifiers(1 << 10);
+    pub const RSUPER: Modifiers = Modifiers(1 << 11);
+
+    // Generic modifiers along with both of their sides
+    const SIDES: [(Modifiers, Modifiers); 4] = [
+        (Self::SHIFT, Modifiers(Self::LSHIFT.0 | Self::RSHIFT.0)),
+        (Self::CTRL, Modifiers(Self::LCTRL.0 | Self::RCTRL.0)),
+        (Self::ALT, Modifiers(Self::LALT.0 | Self::RALT.0)),
+        (Self::SUPER, Modifiers(Self::LSUPER.0 | Self::RSUPER.0)),
+    ];
+
+    pub fn from_key(key: Key) -> Option<Modifiers> {
+        use self::Key::*;
+        match key {
+            Shift => Some(Self::SHIFT),
+            LShift => Some(Self::LSHIFT),
+            RShift => Some(Self::RSHIFT),
+            Ctrl => Some(Self::CTRL),
+            LCtrl => Some(Self::LCTRL),
+            RCtrl => Some(Self::RCTRL),
+            Alt => Some(Self::ALT),
+            LAlt => Some(Self::LALT),
+            RAlt => Some(Self::RALT),
+            Super => Some(Self::SUPER),
+            LSuper => Some(Self::LSUPER),
+            RSuper => Some(Self::RSUPER),
+            _ => None,
+        }
+    }
+    // All ways keybindings can name these modifiers, held sides are replaced by
+    // generic modifiers. The most specific ones come first.
+    pub fn variants(self) -> Vec<Modifiers> {
+        let mut variants = vec![self];
+        for (generic, sides) in &Self::SIDES {
+            if self.0 & sides.0 != 0 {
+                let generalized: Vec<Modifiers> = variants
+                    .iter()
+                    .map(|m| Modifiers((m.0 & !sides.0) | generic.0))
+                    .collect();
+                variants.extend(generalized);
+            }
+        }
+        variants.sort_by_key(|m| std::cmp::Reverse(m.sides()));
+        variants
+    }
+    // Number of modifiers bound to a side
+    fn sides(self) -> u32 {
+        Self::SIDES
+            .iter()
+            .map(|(_, sides)| (self.0 & sides.0).count_ones())
+            .sum()
+    }
+    pub fn insert(&mut self, other: Modifiers) {
+        self.0 |= other.0;
+    }
+    pub fn contains(self, other: Modifiers) -> bool {
+        self.0 & other.0 == other.0
+    }
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+impl BitOr for Modifiers {
+    type Output = Modifiers;
+    fn bitor(self, rhs: Modifiers) -> Modifiers {
+        Modifiers(self.0 | rhs.0)
+    }
+}
+
+/// A keybinding - a set of held modifiers and a single trigger key.
+///
+/// Two chords are equal no matter in which order their keys were written or pressed.
+/// Release chords (`@super + q`) are completed by releasing the trigger key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Chord {
+    pub modifiers: Modifiers,
+    pub key: Key,
+    pub release: bool,
+}
+impl Chord {
+    pub fn new(modifiers: Modifiers, key: Key) -> Chord {
+        Chord {
+            modifiers,
+            key,
+            release: false,
+        }
+    }
+    // The same chord completed by releasing its trigger key
+    pub fn on_release(self) -> Chord {
+        Chord {
+            release: true,
+            ..self
+        }
+    }
+    // Chords of keybindings matched by this pressed chord, most specific first
+    pub fn variants(self) -> Vec<Chord> {
+        self.modifiers
+            .variants()
+            .into_iter()
+            .map(|modifiers| Chord { modifiers, ..self })
+            .collect()
+    }
+    // Builds a chord from a list of keys where exactly one of them is not a modifier
+    pub fn from_keys(keys: &[Key]) -> Option<Chord> {
+        let mut modifiers = Modifiers::NONE;
+        let mut trigger = None;
+        for key in keys {
+            match Modifiers::from_key(*key) {
+                Some(m) => modifiers.insert(m),
+                None => {
+                    if trigger.replace(*key).is_some() {
+                        return None;
+                    }
+                }
+            }
+        }
+        trigger.map(|key| Chord::new(modifiers, key))
+    }
+}
+
+impl fmt::Display for Chord {
+    // Formats the chord like it would be written in a config file - `super + shift + q`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.release {
+            f.write_str("@")?;
+        }
+        for (modifier, name) in &[
+            (Modifiers::SUPER, Key::Super),
+            (Modifiers::LSUPER, Key::LSuper),
+            (Modifiers::RSUPER, Key::RSuper),
+            (Modifiers::CTRL, Key::Ctrl),
+            (Modifiers::LCTRL, Key::LCtrl),
+            (Modifiers::RCTRL, Key::RCtrl),
+            (Modifiers::ALT, Key::Alt),
+            (Modifiers::LALT, Key::LAlt),
+            (Modifiers::RALT, Key::RAlt),
+            (Modifiers::SHIFT, Key::Shift),
+            (Modifiers::LSHIFT, Key::LShift),
+            (Modifiers::RSHIFT, Key::RShift),
+        ] {
+            if self.modifiers.contains(*modifier) {
+                write!(f, "{} + ", name)?;
+            }
+        }
+        write!(f, "{}", self.key)
+    }
+}
+
+/// Chords pressed one after another - `super + w ; h`, most keybindings consist
+/// of a single chord.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Chain(Vec<Chord>);
+impl Chain {
+    pub fn new(chords: Vec<Chord>) -> Chain {
+        Chain(chords)
+    }
+    pub fn chords(&self) -> &[Chord] {
+        &self.0
+    }
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    pub fn last(&self) -> Option<&Chord> {
+        self.0.last()
+    }
+    // Whether the chain begins with all chords of `other` and has more of them
+    pub fn continues(&self, other: &Chain) -> bool {
+        self.0.len() > other.0.len() && self.0.starts_with(&other.0)
+    }
+}
+impl From<Chord> for Chain {
+    fn from(chord: Chord) -> Chain {
+        Chain(vec![chord])
+    }
+}
+impl fmt::Display for Chain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, chord) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ; ")?;
+            }
+            write!(f, "{}", chord)?;
+        }
+        Ok(())
+    }
+}
+
+/// Keeps track of currently held keys of a single input device and
+/// tells which chord, if any, is formed after each key press or released
+/// with each key release.
+#[derive(Debug, Default)]
+pub struct KeyState {
+    modifiers: Vec<Key>,
+    keys: Vec<Key>,
+    // Chord formed by the last key press, until its trigger key is released
+    pressed: Option<Chord>,
+}
+impl KeyState {
+    // Registers a key press and returns the chord formed by all currently held keys.
+    // Returns None if more than one non modifier key is held.
+    pub fn press(&mut self, key: Key) -> Option<Chord> {
+        if Modifiers::from_key(key).is_some() {
+            self.modifiers.push(key);
+        } else if !self.keys.contains(&key) {
+            self.keys.push(key);
+        }
+        self.pressed = self.chord();
+        self.pressed
+    }
+    // Registers a key release and returns the release chord if the key is the trigger
+    // key of the last pressed chord. Modifiers released before it don't matter.
+    pub fn release(&mut self, key: Key) -> Option<Chord> {
+        if let Some(idx) = self.modifiers.iter().position(|k| *k == key) {
+            self.modifiers.remove(idx);
+        }
+        self.keys.retain(|k| *k != key);
+        match self.pressed {
+            Some(chord) if chord.key == key => self.pressed.take().map(Chord::on_release),
+            _ => None,
+        }
+    }
+    pub fn modifiers(&self) -> Modifiers {
+        let mut modifiers = Modifiers::NONE;
+        self.modifiers
+            .iter()
+            .filter_map(|k| Modifiers::from_key(*k))
+            .for_each(|m| modifiers.insert(m));
+        modifiers
+    }
+    pub fn chord(&self) -> Option<Chord> {
+        match self.keys.as_slice() {
+            [key] => Some(Chord::new(self.modifiers(), *key)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use self::Key::*;
+    use super::*;
+    use std::collections::HashMap;
+
+    fn permutations(keys: &[Key]) -> Vec<Vec<Key>> {
+        if keys.len() <= 1 {
+            return vec![keys.to_vec()];
+        }
+        let mut perms = Vec::new();
+        for i in 0..keys.len() {
+            let mut rest = keys.to_vec();
+            let first = rest.remove(i);
+            for mut perm in permutations(&rest) {
+                perm.insert(0, first);
+                perms.push(perm);
+            }
+        }
+        perms
+    }
+
+    fn keybindings() -> Keybindings {
+        let mut keybindings = HashMap::new();
+        for (keys, cmd) in &[
+            (vec![Super, Return], "termite"),
+            (vec![Super, Shift, Q], "bspc node -k"),
+            (vec![Ctrl, Alt, L], "i3lock"),
+            (vec![Ctrl, Alt, Shift, Super, F1], "all"),
+            (vec![XF86AudioPlay], "playerctl play"),
+        ] {
+            let action = Action {
+                exec: Some(Exec::new(cmd).unwrap()),
+                repeat: Repeat::Never,
+                enter: None,
+            };
+            keybindings.insert(Chord::from_keys(keys).unwrap().into(), action);
+        }
+        keybindings
+    }
+
+    // Replays a sequence of presses and returns all chords that matched a keybinding
+    fn replay(keybindings: &Keybindings, presses: &[Key]) -> Vec<Chord> {
+        let mut state = KeyState::default();
+        presses
+            .iter()
+            .filter_map(|k| state.press(*k))
+            .filter(|chord| keybindings.contains_key(&Chain::from(*chord)))
+            .collect()
+    }
+
+    #[test]
+    fn matches_chords_in_any_press_order() {
+        let keybindings = keybindings();
+        let table = vec![
+            vec![Super, Return],
+            vec![Super, Shift, Q],
+            vec![Ctrl, Alt, L],
+            vec![Ctrl, Alt, Shift, Super, F1],
+            vec![XF86AudioPlay],
+        ];
+        for keys in table {
+            let expected = Chord::from_keys(&keys).unwrap();
+            for presses in permutations(&keys) {
+                println!("replaying {:?}", presses);
+                assert_eq!(replay(&keybindings, &presses), vec![expected]);
+            }
+        }
+    }
+
+    #[test]
+    fn doesnt_match_missing_or_extra_keys() {
+        let keybindings = keybindings();
+        let table = vec![
+            vec![Shift, Q],
+            vec![Super],
+            vec![Super, Return, A],
+            vec![Ctrl, Alt, Super, L],
+            vec![Super, XF86AudioPlay],
+            vec![Return],
+        ];
+        for keys in table {
+            for presses in permutations(&keys) {
+                println!("replaying {:?}", presses);
+                let mut state = KeyState::default();
+                let last = presses.iter().map(|k| state.press(*k)).last().unwrap();
+                assert!(last.is_none_or(|chord| !keybindings.contains_key(&chord.into())));
+            }
+        }
+    }
+
+    #[test]
+    fn tracks_releases() {
+        let mut state = KeyState::default();
+        assert_eq!(state.press(Super), None);
+        assert_eq!(state.press(Shift), None);
+        assert_eq!(
+            state.press(Q),
+            Some(Chord::new(Modifiers::SUPER | Modifiers::SHIFT, Q))
+        );
+        state.release(Q);
+        state.release(Shift);
+        assert_eq!(state.press(Q), Some(Chord::new(Modifiers::SUPER, Q)));
+        state.release(Super);
+        state.release(Q);
+        assert_eq!(state.modifiers(), Modifiers::NONE);
+        assert_eq!(state.chord(), None);
+    }
+
+    #[test]
+    fn builds_chords_from_keys() {
+        assert_eq!(
+            Chord::from_keys(&[Shift, Super, Q]),
+            Chord::from_keys(&[Q, Super, Shift])
+        );
+        assert_eq!(Chord::from_keys(&[Shift, Super]), None);
+        assert_eq!(Chord::from_keys(&[Super, A, B]), None);
+        assert_eq!(
+            Chord::from_keys(&[XF86AudioMute]),
+            Some(Chord::new(Modifiers::NONE, XF86AudioMute))
+        );
+    }
+
+    #[test]
+    fn matches_modifier_sides() {
+        let mut state = KeyState::default();
+        state.press(RCtrl);
+        state.press(LShift);
+        let chord = state.press(P).unwrap();
+        assert_eq!(chord.modifiers, Modifiers::RCTRL | Modifiers::LSHIFT);
+        assert_eq!(chord.to_string(), "rctrl + lshift + p");
+        let variants: Vec<String> = chord.variants().iter().map(|c| c.to_string()).collect();
+        assert_eq!(
+            variants,
+            vec![
+                "rctrl + lshift + p",
+                "rctrl + shift + p",
+                "ctrl + lshift + p",
+                "ctrl + shift + p"
+            ]
+        );
+        assert_eq!(
+            Chord::from_keys(&[Super, Q]).unwrap().variants(),
+            vec![Chord::from_keys(&[Super, Q]).unwrap()]
+        );
+    }
+
+    #[test]
+    fn releases_chords() {
+        let mut state = KeyState::default();
+        let super_t = Chord::from_keys(&[Super, T]).unwrap();
+        state.press(Super);
+        assert_eq!(state.press(T), Some(super_t));
+        // modifiers released before the trigger key don't change the chord
+        assert_eq!(state.release(Super), None);
+        assert_eq!(state.release(T), Some(super_t.on_release()));
+        assert_eq!(state.release(T), None);
+        assert_eq!(super_t.on_release().to_string(), "@super + t");
+
+        // pressing another key interrupts the chord
+        state.press(Super);
+        state.press(A);
+        state.press(B);
+        assert_eq!(state.release(A), None);
+        assert_eq!(state.release(B), None);
+    }
+}
@@ -1,106 +1,702 @@
 use super::*;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 
-#[derive(Debug)]
-pub struct Exec {
-    cmd: Command,
-    args: Vec<String>,
-    _first_launch: bool,
+pub type Keybindings = HashMap<Chain, Action>;
+/// Keybindings of every mode by its name, keybindings written before any `mode`
+/// line belong to the default mode.
+pub type Modes = HashMap<String, Keybindings>;
+pub const DEFAULT_MODE: &str = "default";
+// Starts a section of keybindings of a mode - `mode resize`
+const MODE_HEADER: &str = "mode";
+// Starts a section of keybindings of some keyboards - `device name="Macro Pad"`
+const DEVICE_HEADER: &str = "device";
+// Ends device sections, `device *` keybindings apply to all keyboards
+const ANY_DEVICE: &str = "*";
+// Sets the XKB layout keysyms are resolved with - `keyboard layout=de`
+const KEYBOARD_DIRECTIVE: &str = "keyboard";
+// Reads the layout from /etc/default/keyboard - `keyboard system`
+const SYSTEM_KEYBOARD: &str = "system";
+// Separates keys of a keybinding from its options - `super + l | repeat`
+const OPTIONS_SEPARATOR: char = '|';
+// Marks keybindings triggered by releasing the chord - `@super + q`
+const RELEASE_PREFIX: char = '@';
+// Separates chords of a chain - `super + w ; h`
+const CHAIN_SEPARATOR: char = ';';
+
+/// All keybindings of a config file, keybindings of `device` sections are kept
+/// apart from those applying to every keyboard.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Config {
+    pub modes: Modes,
+    pub scopes: Vec<Scope>,
 }
-impl Exec {
-    pub fn new<S: AsRef<str>>(cmd: S) -> Option<Exec> {
-        trace!("Creating exec instance from '{}'", cmd.as_ref());
-        let mut _cmd = cmd.as_ref().split(' ');
-        if let Some(cmd) = _cmd.next() {
-            return Some(Exec {
-                cmd: Command::new(cmd),
-                args: _cmd.map(|arg| arg.to_string()).collect(),
-                _first_launch: true,
-            });
+impl Config {
+    pub fn has_mode(&self, mode: &str) -> bool {
+        self.modes.contains_key(mode)
+            || self
+                .scopes
+                .iter()
+                .any(|scope| scope.modes.contains_key(mode))
+    }
+    // Keybindings of the mode that apply to the keyboard, those of device sections
+    // come first. `links` are names of /dev/input/by-id links of the keyboard.
+    pub fn keybindings(
+        &self,
+        mode: &str,
+        keyboard: &Keyboard,
+        links: &[String],
+    ) -> Vec<&Keybindings> {
+        self.scopes
+            .iter()
+            .filter(|scope| scope.filter.matches(keyboard, links))
+            .map(|scope| &scope.modes)
+            .chain(std::iter::once(&self.modes))
+            .filter_map(|modes| modes.get(mode))
+            .collect()
+    }
+    // Keybindings of the device section with the filter
+    pub fn scoped_modes(&self, filter: &DeviceFilter) -> Option<&Modes> {
+        self.scopes
+            .iter()
+            .find(|scope| scope.filter == *filter)
+            .map(|scope| &scope.modes)
+    }
+    // Keybindings of every mode and device section
+    pub fn all_keybindings(&self) -> impl Iterator<Item = &Keybindings> {
+        self.modes
+            .values()
+            .chain(self.scopes.iter().flat_map(|scope| scope.modes.values()))
+    }
+}
+
+/// What happens when the chord of a keybinding is pressed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Action {
+    pub exec: Option<Exec>,
+    pub repeat: Repeat,
+    // Mode entered after running the command
+    pub enter: Option<String>,
+}
+
+/// A parsed keybinding along with the line of the config file it was defined at.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Binding {
+    pub chain: Chain,
+    pub exec: Option<Exec>,
+    pub repeat: Repeat,
+    // Mode the keybinding belongs to
+    pub mode: String,
+    // Keyboards the keybinding applies to, all of them if it's not set
+    pub device: Option<DeviceFilter>,
+    pub enter: Option<String>,
+    pub line: usize,
+    pub source: String,
+}
+
+// Options of a keybinding separated by commas
+#[derive(Debug, Default)]
+struct Options {
+    repeat: Repeat,
+    enter: Option<String>,
+}
+impl Options {
+    fn parse(options: &str) -> Result<Options, String> {
+        let mut parsed = Options::default();
+        for option in options.split(',').map(str::trim) {
+            match option.split_whitespace().next() {
+                Some("repeat") | Some("norepeat") => parsed.repeat = Repeat::parse(option)?,
+                Some(MODE_HEADER) => match parse_mode_name(option) {
+                    Some(name) => parsed.enter = Some(name.to_string()),
+                    None => return Err(format!("expected '{} <name>'", MODE_HEADER)),
+                },
+                _ => return Err(format!("unknown option '{}'", option)),
+            }
         }
-        None
+        Ok(parsed)
     }
-    pub fn run(&mut self) -> io::Result<Child> {
-        if self._first_launch {
-            self._first_launch = false;
-            self.cmd.args(&self.args).spawn()
-        } else {
-            self.cmd.spawn()
+}
+
+// A keybinding line along with the lines of its command as written in a config file
+#[derive(Debug)]
+struct Entry {
+    line: usize,
+    mode: String,
+    device: Option<DeviceFilter>,
+    keybinding: String,
+    cmd_line: usize,
+    cmd_source: String,
+    cmd: Vec<String>,
+}
+impl Entry {
+    fn new(line: usize, mode: &str, device: &Option<DeviceFilter>, keybinding: String) -> Entry {
+        Entry {
+            line,
+            mode: mode.to_string(),
+            device: device.clone(),
+            keybinding,
+            cmd_line: line,
+            cmd_source: String::new(),
+            cmd: Vec::new(),
         }
     }
 }
 
-pub type Keybindings = HashMap<Vec<Key>, Exec>;
+// Returns the name of the mode from a `mode <name>` line, names are single words
+fn parse_mode_name(line: &str) -> Option<&str> {
+    let mut words = line.split_whitespace();
+    match (words.next(), words.next(), words.next()) {
+        (Some(MODE_HEADER), Some(name), None) => Some(name),
+        _ => None,
+    }
+}
+
+// Number of bytes of leading whitespace
+fn indent_width(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
 
 #[derive(Clone)]
 pub struct Cfg<P: AsRef<Path>> {
     cfg_file: P,
+    exec_mode: ExecMode,
 }
 impl<P: AsRef<Path>> Cfg<P> {
     pub fn new(cfg_file: P) -> Self {
-        Self { cfg_file }
-    }
-    pub fn parse(&self) -> io::Result<Keybindings> {
-        let file_content = fs::read_to_string(self.cfg_file.as_ref())?;
-        let lines = file_content.split('\n');
-        let mut keybindings = HashMap::new();
-        let mut current_kb = Vec::new();
-        let mut current_cmd = String::new();
-        let mut was_keybinding = false;
-        let mut was_cmd = false;
-        lines.for_each(|line| {
-            if Self::is_keybinding(&line) && !was_keybinding {
-                if let Some(kb) = Self::parse_keybinding(&line) {
-                    current_kb = kb;
-                    was_keybinding = true;
-                    if was_cmd {
-                        was_cmd = false;
+        Self {
+            cfg_file,
+            exec_mode: ExecMode::default(),
+        }
+    }
+    pub fn file(&self) -> &Path {
+        self.cfg_file.as_ref()
+    }
+    // Sets how commands of parsed keybindings will be executed
+    pub fn exec_mode(mut self, exec_mode: ExecMode) -> Self {
+        self.exec_mode = exec_mode;
+        self
+    }
+    pub fn parse(&self) -> Result<Config, Vec<ConfigError>> {
+        let config = Self::into_config(self.bindings()?);
+        info!("{:?}", config);
+        Ok(config)
+    }
+    pub fn parse_str(&self, content: &str) -> Result<Config, Vec<ConfigError>> {
+        self.bindings_from_str(content).map(Self::into_config)
+    }
+    // Reads the config file and returns all of its keybindings in the order they were defined
+    pub fn bindings(&self) -> Result<Vec<Binding>, Vec<ConfigError>> {
+        let file_content = fs::read_to_string(self.cfg_file.as_ref()).map_err(|e| {
+            vec![ConfigError::new(ConfigErrorKind::Io(e), "").location(&self.cfg_file, 0, "")]
+        })?;
+        self.bindings_from_str(&file_content)
+    }
+    // Groups keybindings by their devices and modes, the default mode of keybindings
+    // applying to all keyboards is always present
+    fn into_config(bindings: Vec<Binding>) -> Config {
+        let mut config = Config::default();
+        config
+            .modes
+            .insert(DEFAULT_MODE.to_string(), Keybindings::new());
+        for b in bindings {
+            let action = Action {
+                exec: b.exec,
+                repeat: b.repeat,
+                enter: b.enter,
+            };
+            let modes = match b.device {
+                None => &mut config.modes,
+                Some(filter) => match config.scopes.iter().position(|s| s.filter == filter) {
+                    Some(i) => &mut config.scopes[i].modes,
+                    None => {
+                        config.scopes.push(Scope {
+                            filter,
+                            modes: Modes::new(),
+                        });
+                        &mut config.scopes.last_mut().unwrap().modes
                     }
+                },
+            };
+            modes.entry(b.mode).or_default().insert(b.chain, action);
+        }
+        config
+    }
+
+    // Parses keybindings from the content of a config file. Every keybinding line
+    // is followed by one or more indented lines that make up the body of the command.
+    // Keybindings following a `mode <name>` line belong to that mode, those following
+    // a `device <filter>` line only apply to matching keyboards and a `keyboard`
+    // line sets the layout characters of all keybindings follow.
+    // All problems found in the content are returned at once.
+    pub fn bindings_from_str(&self, content: &str) -> Result<Vec<Binding>, Vec<ConfigError>> {
+        let mut errors = Vec::new();
+        let mut entries: Vec<Entry> = Vec::new();
+        let mut indent = String::new();
+        let mut mode = DEFAULT_MODE.to_string();
+        let mut device = None;
+        // whether indented lines continue the last entry
+        let mut in_entry = false;
+        let mut keyboard: Option<(usize, String)> = None;
+
+        for (line_no, line) in Self::logical_lines(content) {
+            if Self::is_comment(&line) || line.trim().is_empty() {
+                continue;
+            } else if Self::is_mode_header(&line) {
+                in_entry = false;
+                match parse_mode_name(&line) {
+                    Some(name) => mode = name.to_string(),
+                    None => errors.push(
+                        ConfigError::new(ConfigErrorKind::InvalidMode, line.trim()).location(
+                            &self.cfg_file,
+                            line_no,
+                            &line,
+                        ),
+                    ),
                 }
-            } else if Self::is_cmd(&line) && !was_cmd && was_keybinding {
-                current_cmd = line.trim().to_string();
-                if let Some(exec) = Exec::new(&current_cmd) {
-                    keybindings.insert(current_kb.clone(), exec);
+            } else if Self::is_device_header(&line) {
+                in_entry = false;
+                let args = line.trim_start()[DEVICE_HEADER.len()..].trim();
+                if args == ANY_DEVICE {
+                    device = None;
+                } else {
+                    match DeviceFilter::parse(args) {
+                        Ok(filter) => device = Some(filter),
+                        Err(e) => errors.push(
+                            ConfigError::new(ConfigErrorKind::InvalidDevice(e), line.trim())
+                                .location(&self.cfg_file, line_no, &line),
+                        ),
+                    }
+                }
+            } else if Self::is_keyboard_directive(&line) {
+                in_entry = false;
+                if let Some((defined_at, _)) = &keyboard {
+                    let e = format!("the keyboard is already set at line {}", defined_at);
+                    errors.push(
+                        ConfigError::new(ConfigErrorKind::InvalidKeyboard(e), line.trim())
+                            .location(&self.cfg_file, line_no, &line),
+                    );
+                } else {
+                    keyboard = Some((line_no, line));
+                }
+            } else if Self::is_keybinding(&line) {
+                in_entry = true;
+                entries.push(Entry::new(line_no, &mode, &device, line));
+            } else if let Some(entry) = entries.last_mut().filter(|_| in_entry) {
+                if entry.cmd.is_empty() {
+                    indent = line[..indent_width(&line)].to_string();
+                    entry.cmd_line = line_no;
+                    entry.cmd_source = line.clone();
                 }
-                current_kb.clear();
-                current_cmd.clear();
-                was_cmd = true;
-                was_keybinding = false;
+                entry
+                    .cmd
+                    .push(Self::strip_indent(&line, &indent).to_string());
+            } else {
+                errors.push(
+                    ConfigError::new(ConfigErrorKind::OrphanCommand, line.trim())
+                        .column(indent_width(&line) + 1)
+                        .location(&self.cfg_file, line_no, &line),
+                );
             }
-        });
-        info!("{:?}", keybindings);
-        Ok(keybindings)
+        }
+
+        let keymap =
+            keyboard.and_then(|(line_no, line)| match Self::keymap(&line) {
+                Ok(keymap) => Some(keymap),
+                Err(e) => {
+                    errors.push(
+                        ConfigError::new(ConfigErrorKind::InvalidKeyboard(e), line.trim())
+                            .location(&self.cfg_file, line_no, &line),
+                    );
+                    None
+                }
+            });
+
+        let mut bindings: Vec<Binding> = Vec::new();
+        let mut defined_at = HashMap::new();
+        for entry in entries {
+            match self.parse_entry(&entry, keymap.as_ref()) {
+                Ok(parsed) => {
+                    for binding in parsed {
+                        let key = (
+                            binding.mode.clone(),
+                            binding.device.clone(),
+                            binding.chain.clone(),
+                        );
+                        if let Some(line) = defined_at.insert(key, binding.line) {
+                            errors.push(
+                                ConfigError::new(
+                                    ConfigErrorKind::DuplicateKeybinding(line),
+                                    binding.chain.to_string(),
+                                )
+                                .location(
+                                    &self.cfg_file,
+                                    binding.line,
+                                    &binding.source,
+                                ),
+                            );
+                        } else {
+                            bindings.push(binding);
+                        }
+                    }
+                }
+                Err(e) => errors.extend(e),
+            }
+        }
+        errors.extend(self.undefined_modes(&bindings));
+
+        if errors.is_empty() {
+            Ok(bindings)
+        } else {
+            errors.sort_by_key(|e| e.line);
+            Err(errors)
+        }
+    }
+
+    // Finds keybindings entering modes that have no keybindings
+    fn undefined_modes(&self, bindings: &[Binding]) -> Vec<ConfigError> {
+        bindings
+            .iter()
+            .filter_map(|b| b.enter.as_ref().map(|mode| (b, mode)))
+            .filter(|(_, mode)| {
+                *mode != DEFAULT_MODE && !bindings.iter().any(|other| &other.mode == *mode)
+            })
+            .map(|(b, mode)| {
+                let column = b.source.rfind(mode.as_str()).unwrap_or(0);
+                ConfigError::new(ConfigErrorKind::UndefinedMode, mode.as_str())
+                    .column(b.source[..column].chars().count() + 1)
+                    .location(&self.cfg_file, b.line, &b.source)
+            })
+            .collect()
+    }
+
+    // Expands and parses a single keybinding with its command
+    fn parse_entry(
+        &self,
+        entry: &Entry,
+        keymap: Option<&Keymap>,
+    ) -> Result<Vec<Binding>, Vec<ConfigError>> {
+        let kb_location =
+            |e: ConfigError| e.location(&self.cfg_file, entry.line, &entry.keybinding);
+        // keybindings that only switch modes don't need a command
+        let enters_mode = entry
+            .keybinding
+            .get(Self::options_start(&entry.keybinding)..)
+            .and_then(|options| Options::parse(options).ok())
+            .is_some_and(|options| options.enter.is_some());
+        if entry.cmd.is_empty() && !enters_mode {
+            return Err(vec![kb_location(ConfigError::new(
+                ConfigErrorKind::MissingCommand,
+                entry.keybinding.trim(),
+            ))]);
+        }
+
+        let expanded = expand(&entry.keybinding, &entry.cmd.join("\n")).map_err(|e| {
+            vec![kb_location(ConfigError::new(
+                ConfigErrorKind::InvalidSequence(e),
+                entry.keybinding.trim(),
+            ))]
+        })?;
+
+        let mut parsed = Vec::new();
+        let mut errors = Vec::new();
+        for (kb, cmd) in expanded {
+            let (keys, options) = match Self::find_options_separator(&kb) {
+                Some(i) => (&kb[..i], Some(&kb[i + 1..])),
+                None => (kb.as_str(), None),
+            };
+            let options = options
+                .map(Options::parse)
+                .unwrap_or_else(|| Ok(Options::default()));
+            let options = options.map_err(|e| {
+                let options_start = Self::options_start(&entry.keybinding);
+                let options = &entry.keybinding[options_start..];
+                ConfigError::new(ConfigErrorKind::InvalidOption(e), options.trim()).column(
+                    entry.keybinding[..options_start].chars().count() + indent_width(options) + 1,
+                )
+            });
+            let single_key = entry.mode != DEFAULT_MODE || entry.device.is_some();
+            let chain = Self::parse_chords(keys, single_key, keymap).map_err(|e| {
+                // the column refers to the expanded keybinding, point to the token
+                // in the original line if the keybinding was expanded
+                let column = if kb == entry.keybinding {
+                    e.column
+                } else {
+                    entry
+                        .keybinding
+                        .find(&e.token)
+                        .map(|i| entry.keybinding[..i].chars().count() + 1)
+                        .unwrap_or(1)
+                };
+                kb_location(e.column(column))
+            });
+            let exec = if entry.cmd.is_empty() {
+                Ok(None)
+            } else {
+                Exec::with_mode(&cmd, &self.exec_mode)
+                    .map(Some)
+                    .map_err(|e| {
+                        ConfigError::new(
+                            ConfigErrorKind::InvalidCommand(e),
+                            entry.cmd_source.trim(),
+                        )
+                        .column(indent_width(&entry.cmd_source) + 1)
+                        .location(
+                            &self.cfg_file,
+                            entry.cmd_line,
+                            &entry.cmd_source,
+                        )
+                    })
+            };
+            match (chain, options.map_err(kb_location), exec) {
+                (Ok(chain), Ok(options), Ok(exec)) => parsed.push(Binding {
+                    chain,
+                    exec,
+                    repeat: options.repeat,
+                    mode: entry.mode.clone(),
+                    device: entry.device.clone(),
+                    enter: options.enter,
+                    line: entry.line,
+                    source: entry.keybinding.clone(),
+                }),
+                (chain, options, exec) => errors.extend(
+                    chain
+                        .err()
+                        .into_iter()
+                        .chain(options.err())
+                        .chain(exec.err()),
+                ),
+            }
+        }
+
+        // the same problem in an expanded keybinding is reported only once
+        errors.dedup_by(|a, b| a.message() == b.message() && a.line == b.line);
+        if errors.is_empty() {
+            Ok(parsed)
+        } else {
+            Err(errors)
+        }
+    }
+
+    // Joins lines ending with a backslash with the following line and
+    // returns them along with the number of the first line they start at.
+    // Comments aren't continued.
+    fn logical_lines(content: &str) -> Vec<(usize, String)> {
+        let mut lines = Vec::new();
+        let mut current: Option<(usize, String)> = None;
+        for (i, line) in content.lines().enumerate() {
+            let (line_no, mut logical) = match current.take() {
+                Some((line_no, mut logical)) => {
+                    logical.push_str(line.trim_start());
+                    (line_no, logical)
+                }
+                None => (i + 1, line.to_string()),
+            };
+            if Self::is_continued(&logical) {
+                logical.pop();
+                current = Some((line_no, logical));
+            } else {
+                lines.push((line_no, logical));
+            }
+        }
+        if let Some(last) = current {
+            lines.push(last);
+        }
+        lines
+    }
+
+    // Whether the line ends with a backslash joining it with the next line. In
+    // keybindings a backslash where a key is expected is the backslash key -
+    // `super + \`.
+    fn is_continued(line: &str) -> bool {
+        let keys = match line.strip_suffix('\\') {
+            Some(keys) if !Self::is_comment(line) => keys.trim_end(),
+            _ => return false,
+        };
+        Self::is_cmd(line)
+            || !(keys.is_empty() || keys.ends_with(['+', CHAIN_SEPARATOR, RELEASE_PREFIX]))
+    }
+
+    // Removes indentation of the first line of a command body from the line, lines
+    // that are indented differently have all of their leading whitespace removed.
+    fn strip_indent<'a>(line: &'a str, indent: &str) -> &'a str {
+        match line.strip_prefix(indent) {
+            Some(stripped) => stripped.trim_end(),
+            None => line.trim(),
+        }
     }
 
+    pub fn is_comment(line: &str) -> bool {
+        line.starts_with('#')
+    }
+    pub fn is_keyboard_directive(line: &str) -> bool {
+        Self::is_directive(line, KEYBOARD_DIRECTIVE)
+    }
+    pub fn is_mode_header(line: &str) -> bool {
+        Self::is_directive(line, MODE_HEADER)
+    }
+    pub fn is_device_header(line: &str) -> bool {
+        Self::is_keybinding(line) && line.split_whitespace().next() == Some(DEVICE_HEADER)
+    }
+    // Directives start in the first column, indented lines belong to commands.
+    // `mode` and `keyboard` are also key names, followed by nothing or by `+`, `;`
+    // or `|` they start a keybinding - `mode + a`.
+    fn is_directive(line: &str, name: &str) -> bool {
+        let mut words = line.split_whitespace();
+        Self::is_keybinding(line)
+            && words.next() == Some(name)
+            && words
+                .next()
+                .is_some_and(|word| !word.starts_with(['+', CHAIN_SEPARATOR, OPTIONS_SEPARATOR]))
+    }
     pub fn is_keybinding(line: &str) -> bool {
-        !(line.starts_with(' ') || line.starts_with('\t')) && line != ""
+        !(line.starts_with(' ') || line.starts_with('\t') || line.is_empty())
     }
     pub fn is_cmd(line: &str) -> bool {
         line.starts_with(' ') || line.starts_with('\t')
     }
 
-    pub fn parse_keybinding(line: &str) -> Option<Vec<Key>> {
+    // Parses the chords of a keybinding line separated by `;`, the column of a returned
+    // error refers to the line
+    #[allow(clippy::result_large_err)]
+    pub fn parse_chain(line: &str) -> Result<Chain, ConfigError> {
+        Self::parse_chords(line, false, None)
+    }
+
+    // Compiles the keymap of a `keyboard layout=de variant=nodeadkeys` line
+    fn keymap(line: &str) -> Result<Keymap, String> {
+        let args = line.trim_start()[KEYBOARD_DIRECTIVE.len()..].trim();
+        let names = if args == SYSTEM_KEYBOARD {
+            fs::read_to_string(DEFAULT_KEYBOARD)
+                .map(|content| LayoutNames::from_default_keyboard(&content))
+                .map_err(|e| format!("failed to read {} - {}", DEFAULT_KEYBOARD, e))?
+        } else {
+            LayoutNames::parse(args)?
+        };
+        Keymap::new(&names).map_err(|e| e.to_string())
+    }
+
+    // Keybindings of modes other than the default one and of device sections may
+    // start with a single key
+    #[allow(clippy::result_large_err)]
+    fn parse_chords(
+        line: &str,
+        single_key: bool,
+        keymap: Option<&Keymap>,
+    ) -> Result<Chain, ConfigError> {
+        let mut chords = Vec::new();
+        for (i, (start, segment)) in Self::chain_segments(line).into_iter().enumerate() {
+            let chord = Self::parse_chord(segment, i > 0 || single_key, keymap).map_err(|e| {
+                let column = line[..start].chars().count() + e.column;
+                e.column(column)
+            })?;
+            chords.push(chord);
+        }
+        Ok(Chain::new(chords))
+    }
+
+    // Finds the `|` separating keys from options. Like `;`, a `|` at the start of a
+    // chord or after `+` is the backslash key - `super + | | repeat`.
+    fn find_options_separator(line: &str) -> Option<usize> {
+        line.char_indices().find_map(|(i, ch)| {
+            let chains = Self::chain_segments(&line[..i]);
+            let chord = chains.last().map_or("", |(_, chord)| chord.trim());
+            let is_key =
+                chord.is_empty() || chord.ends_with('+') || chord.ends_with(RELEASE_PREFIX);
+            (ch == OPTIONS_SEPARATOR && !is_key).then_some(i)
+        })
+    }
+
+    // Byte offset of the options following the separator, the end of the line if
+    // there are none
+    fn options_start(line: &str) -> usize {
+        Self::find_options_separator(line).map_or(line.len(), |i| i + 1)
+    }
+
+    // Splits a keybinding line into chords along with their byte offsets. A `;` at the
+    // start of a chord or after `+` is the semicolon key - `super + ; ; h`.
+    fn chain_segments(line: &str) -> Vec<(usize, &str)> {
+        let mut segments = Vec::new();
+        let mut start = 0;
+        for (i, ch) in line.char_indices() {
+            let chord = line[start..i].trim();
+            if ch == CHAIN_SEPARATOR
+                && !chord.is_empty()
+                && !chord.ends_with('+')
+                && !chord.ends_with(RELEASE_PREFIX)
+            {
+                segments.push((start, &line[start..i]));
+                start = i + 1;
+            }
+        }
+        segments.push((start, &line[start..]));
+        segments
+    }
+
+    // Parses a single chord of a keybinding line, the column of a returned error refers
+    // to the chord
+    #[allow(clippy::result_large_err)]
+    pub fn parse_keybinding(line: &str) -> Result<Chord, ConfigError> {
+        Self::parse_chord(line, false, None)
+    }
+
+    // Chords following the first one of a chain may consist of a single key - `super + w ; h`
+    #[allow(clippy::result_large_err)]
+    fn parse_chord(
+        line: &str,
+        single_key: bool,
+        keymap: Option<&Keymap>,
+    ) -> Result<Chord, ConfigError> {
         trace!("parsing keybinding from {}", line);
-        let keys: Vec<&str> = line.split('+').map(|k| k.trim()).collect();
-        let mut parsed_keys = HashSet::new();
-
-        for key in keys {
-            let k = Key::from_str(key);
-            for key in k {
-                if !parsed_keys.insert(key.clone()) {
-                    error!(
-                            "failed to parse keybinding '{}' - all keys have to be unique in a keybinding",
-                            line
-                        );
-                    return None;
+        let mut parsed_keys = Vec::new();
+        let mut offset = 0;
+        // `@` makes it a release chord, it's replaced by a space to keep columns intact
+        let release = line.trim_start().starts_with(RELEASE_PREFIX);
+        let keys = line.replacen(RELEASE_PREFIX, " ", release as usize);
+
+        for segment in keys.split('+') {
+            let token = segment.trim();
+            let column = keys[..offset + indent_width(segment)].chars().count() + 1;
+            offset += segment.len() + 1;
+            for key in Self::parse_token(token, keymap) {
+                if key == Key::UK {
+                    return Err(ConfigError::new(ConfigErrorKind::UnknownKey, token)
+                        .column(column)
+                        .suggestion(Key::closest_name(token).map(String::from)));
+                }
+                // `ctrl` already covers `lctrl` and `rctrl`
+                let covered = |k: &Key| {
+                    k.generic() == key.generic() && (*k == k.generic() || key == key.generic())
+                };
+                if parsed_keys.iter().any(covered) {
+                    return Err(
+                        ConfigError::new(ConfigErrorKind::DuplicateKey, token).column(column)
+                    );
                 }
+                parsed_keys.push(key);
             }
         }
-        if Self::is_valid_keybinding(&parsed_keys.iter().map(|k| *k).collect::<Vec<Key>>()) {
-            Some(parsed_keys.iter().map(|k| *k).collect())
-        } else {
-            error!("invalid keybinding {:?}", &parsed_keys);
-            None
+        // Keys are validated in the order they were written, the resulting
+        // chord doesn't depend on it anymore.
+        let is_single_key = matches!(parsed_keys.as_slice(), [key] if key.is_action());
+        if Self::is_valid_keybinding(&parsed_keys) || (single_key && is_single_key) {
+            if let Some(chord) = Chord::from_keys(&parsed_keys) {
+                return Ok(if release { chord.on_release() } else { chord });
+            }
+        }
+        Err(
+            ConfigError::new(ConfigErrorKind::InvalidKeybinding, line.trim())
+                .column(indent_width(line) + 1),
+        )
+    }
+
+    // With a keymap characters and keysym names follow the layout, names of physical
+    // keys like `esc` or `pageup` don't depend on it
+    fn parse_token(token: &str, keymap: Option<&Keymap>) -> Vec<Key> {
+        let keys = Key::from_str(token);
+        match keymap {
+            Some(keymap) if token.chars().count() == 1 || keys.contains(&Key::UK) => {
+                keymap.keys(token).unwrap_or(keys)
+            }
+            _ => keys,
         }
     }
 
@@ -130,7 +726,8 @@ impl<P: AsRef<Path>> Cfg<P> {
                 } else {
                     // If its not a mod key then its only valid
                     // if its a media control key like XF86AudioRaiseVolume
-                    return first.is_media_control() && keys.len() == 1;
+                    // or a key bound by its number like `code:656`
+                    return (first.is_media_control() || first.is_numbered()) && keys.len() == 1;
                 }
             }
             None => return false,
@@ -167,4 +764,585 @@ mod tests {
             assert_eq!(Cfg::<&Path>::is_valid_keybinding(&kb.0), kb.1)
         });
     }
+
+    fn cmd(body: &str) -> Exec {
+        Exec::with_mode(body, &ExecMode::Shell("sh".to_string())).unwrap()
+    }
+
+    fn parse(content: &str) -> Keybindings {
+        parse_modes(content).remove(DEFAULT_MODE).unwrap()
+    }
+
+    fn parse_modes(content: &str) -> Modes {
+        parse_config(content).modes
+    }
+
+    fn parse_config(content: &str) -> Config {
+        Cfg::new("test")
+            .exec_mode(ExecMode::Shell("sh".to_string()))
+            .parse_str(content)
+            .unwrap()
+    }
+
+    #[test]
+    fn parses_multiline_commands() {
+        let content = "# comment
+super + return
+\ttermite
+super + a
+\tif pgrep -x foo; then
+\t    pkill foo
+\telse
+\t    foo &
+\tfi
+
+super + b
+  echo 1
+\techo 2
+    echo 3
+super + c
+    echo 1 && \\
+        echo 2
+";
+        let keybindings = parse(content);
+        assert_eq!(keybindings.len(), 4);
+        let kb = |keys: &[Key]| {
+            keybindings
+                .get(&Chord::from_keys(keys).unwrap().into())
+                .and_then(|action| action.exec.as_ref())
+        };
+        assert_eq!(kb(&[Key::Super, Key::Return]), Some(&cmd("termite")));
+        assert_eq!(
+            kb(&[Key::Super, Key::A]),
+            Some(&cmd(
+                "if pgrep -x foo; then\n    pkill foo\nelse\n    foo &\nfi"
+            ))
+        );
+        assert_eq!(
+            kb(&[Key::Super, Key::B]),
+            Some(&cmd("echo 1\necho 2\n  echo 3"))
+        );
+        assert_eq!(kb(&[Key::Super, Key::C]), Some(&cmd("echo 1 && echo 2")));
+    }
+
+    #[test]
+    fn expands_sequences() {
+        let content = "super + {_,shift + }{1-3}
+\tbspc {desktop -f,node -d} ^{1-3}
+XF86Audio{Raise,Lower}Volume
+\tpactl set-sink-volume 0 {+,-}5%";
+        let keybindings = parse(content);
+        assert_eq!(keybindings.len(), 8);
+        let kb = |keys: &[Key]| {
+            keybindings
+                .get(&Chord::from_keys(keys).unwrap().into())
+                .and_then(|action| action.exec.as_ref())
+        };
+        assert_eq!(
+            kb(&[Key::Super, Key::Num1]),
+            Some(&cmd("bspc desktop -f ^1"))
+        );
+        assert_eq!(
+            kb(&[Key::Super, Key::Shift, Key::Num3]),
+            Some(&cmd("bspc node -d ^3"))
+        );
+        assert_eq!(
+            kb(&[Key::XF86AudioLowerVolume]),
+            Some(&cmd("pactl set-sink-volume 0 -5%"))
+        );
+    }
+
+    #[test]
+    fn collects_all_errors() {
+        let content = "\techo orphan
+supr + q
+\techo 1
+super + shift + Q
+\techo 2
+super + {a,b}
+\techo {1,2,3}
+super + a + b
+\techo 3
+super + c
+super + {x,yy}
+\techo 4
+super + d
+\techo 'unterminated";
+        let errors = Cfg::new("rkdrc")
+            .exec_mode(ExecMode::Direct)
+            .parse_str(content)
+            .unwrap_err();
+        let summary: Vec<_> = errors
+            .iter()
+            .map(|e| (e.line, e.column, e.token.as_str(), e.suggestion.as_deref()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (1, 2, "echo orphan", None),
+                (2, 1, "supr", Some("super")),
+                (4, 17, "Q", None),
+                (6, 1, "super + {a,b}", None),
+                (8, 1, "super + a + b", None),
+                (10, 1, "super + c", None),
+                (11, 12, "yy", None),
+                (14, 2, "echo 'unterminated", None),
+            ]
+        );
+        assert!(matches!(errors[0].kind, ConfigErrorKind::OrphanCommand));
+        assert!(matches!(errors[1].kind, ConfigErrorKind::UnknownKey));
+        assert!(matches!(errors[2].kind, ConfigErrorKind::DuplicateKey));
+        assert!(matches!(
+            errors[3].kind,
+            ConfigErrorKind::InvalidSequence(_)
+        ));
+        assert!(matches!(errors[4].kind, ConfigErrorKind::InvalidKeybinding));
+        assert!(matches!(errors[5].kind, ConfigErrorKind::MissingCommand));
+        assert!(matches!(errors[6].kind, ConfigErrorKind::UnknownKey));
+        assert!(matches!(errors[7].kind, ConfigErrorKind::InvalidCommand(_)));
+        assert!(errors.iter().all(|e| e.file == Path::new("rkdrc")));
+    }
+
+    #[test]
+    fn rejects_duplicate_keybindings() {
+        let content = "super + q
+\techo 1
+super + {w,q}
+\techo 2";
+        let errors = Cfg::new("rkdrc").parse_str(content).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 3);
+        assert_eq!(errors[0].token, "super + q");
+        assert!(matches!(
+            errors[0].kind,
+            ConfigErrorKind::DuplicateKeybinding(1)
+        ));
+    }
+
+    #[test]
+    fn joins_continued_keybinding_lines() {
+        let content = "super + shift \\
+  + q
+\tbspc node -k
+super + e
+\trofi
+\techo orphan";
+        let keybindings = parse(content);
+        assert_eq!(keybindings.len(), 2);
+        assert_eq!(
+            keybindings
+                .get(
+                    &Chord::from_keys(&[Key::Super, Key::Shift, Key::Q])
+                        .unwrap()
+                        .into()
+                )
+                .and_then(|action| action.exec.as_ref()),
+            Some(&cmd("bspc node -k"))
+        );
+        assert_eq!(
+            keybindings
+                .get(&Chord::from_keys(&[Key::Super, Key::E]).unwrap().into())
+                .and_then(|action| action.exec.as_ref()),
+            Some(&cmd("rofi\necho orphan"))
+        );
+    }
+
+    #[test]
+    fn parses_backslash_key() {
+        let content = "# see C:\\
+super + \\
+\techo bs
+super + w ; \\
+\techo chained
+super + e
+\tcat a \\
+\t  b";
+        let keybindings = parse(content);
+        let exec = |chain: Chain| {
+            keybindings
+                .get(&chain)
+                .and_then(|action| action.exec.clone())
+        };
+        let chord = |keys: &[Key]| Chord::from_keys(keys).unwrap();
+        assert_eq!(
+            exec(chord(&[Key::Super, Key::BackSlash]).into()),
+            Some(cmd("echo bs"))
+        );
+        assert_eq!(
+            exec(Chain::new(vec![
+                chord(&[Key::Super, Key::W]),
+                chord(&[Key::BackSlash])
+            ])),
+            Some(cmd("echo chained"))
+        );
+        assert_eq!(
+            exec(chord(&[Key::Super, Key::E]).into()),
+            Some(cmd("cat a b"))
+        );
+        assert_eq!(keybindings.len(), 3);
+    }
+
+    #[test]
+    fn parses_directives_only_in_first_column() {
+        let modes = parse_modes(
+            "super + a
+\techo start
+\tmode foo
+\tdevice name=x
+\tkeyboard layout=de
+super + b
+\techo b
+mode resize
+mode | mode default
+keyboard
+\techo keyboard key",
+        );
+        let exec = |mode: &str, keys: &[Key]| {
+            modes[mode]
+                .get(&Chord::from_keys(keys).unwrap().into())
+                .and_then(|action| action.exec.clone())
+        };
+        assert_eq!(
+            exec(DEFAULT_MODE, &[Key::Super, Key::A]),
+            Some(cmd(
+                "echo start\nmode foo\ndevice name=x\nkeyboard layout=de"
+            ))
+        );
+        assert_eq!(
+            exec(DEFAULT_MODE, &[Key::Super, Key::B]),
+            Some(cmd("echo b"))
+        );
+        assert_eq!(
+            modes["resize"][&Chord::from_keys(&[Key::Mode]).unwrap().into()].enter,
+            Some(DEFAULT_MODE.to_string())
+        );
+        assert_eq!(
+            exec("resize", &[Key::Keyboard]),
+            Some(cmd("echo keyboard key"))
+        );
+        assert_eq!(modes.len(), 2);
+    }
+
+    #[test]
+    fn parses_repeat_options() {
+        let content = "XF86Audio{Raise,Lower}Volume | repeat
+\tpactl set-sink-volume 0 {+,-}5%
+super + {h,l} | repeat 150ms
+\tbspc node -z {left -20 0,right 20 0}
+super + q | norepeat
+\tbspc node -c
+super + w
+\trofi";
+        let keybindings = parse(content);
+        let repeat = |keys: &[Key]| keybindings[&Chord::from_keys(keys).unwrap().into()].repeat;
+        assert_eq!(repeat(&[Key::XF86AudioLowerVolume]), Repeat::Always);
+        assert_eq!(
+            repeat(&[Key::Super, Key::L]),
+            Repeat::Every(std::time::Duration::from_millis(150))
+        );
+        assert_eq!(repeat(&[Key::Super, Key::Q]), Repeat::Never);
+        assert_eq!(repeat(&[Key::Super, Key::W]), Repeat::Never);
+
+        let errors = Cfg::new("rkdrc")
+            .parse_str("super + a |  repeat often\n\techo\nsuper + b | hold\n\techo")
+            .unwrap_err();
+        let summary: Vec<_> = errors
+            .iter()
+            .map(|e| (e.line, e.column, e.token.as_str()))
+            .collect();
+        assert_eq!(summary, vec![(1, 14, "repeat often"), (3, 13, "hold")]);
+        assert!(matches!(errors[0].kind, ConfigErrorKind::InvalidOption(_)));
+    }
+
+    #[test]
+    fn parses_pipe_key() {
+        let content = "super + |
+\techo pipe
+alt + | | repeat
+\techo repeated
+ctrl + ; | repeat
+\techo semicolon
+super + w ; |
+\techo chained";
+        let keybindings = parse(content);
+        let repeat = |keys: &[Key]| keybindings[&Chord::from_keys(keys).unwrap().into()].repeat;
+        // like uppercase letters `|` adds shift
+        assert_eq!(
+            repeat(&[Key::Super, Key::Shift, Key::BackSlash]),
+            Repeat::Never
+        );
+        assert_eq!(
+            repeat(&[Key::Alt, Key::Shift, Key::BackSlash]),
+            Repeat::Always
+        );
+        assert_eq!(repeat(&[Key::Ctrl, Key::SemiColon]), Repeat::Always);
+        assert_eq!(keybindings.len(), 4);
+    }
+
+    #[test]
+    fn parses_release_keybindings() {
+        let content = "super + t
+\tpactl set-source-mute @DEFAULT_SOURCE@ 0
+@super + t
+\tpactl set-source-mute @DEFAULT_SOURCE@ 1
+@super + {_,shift + }s
+\tmaim {_,-s} ~/shot.png";
+        let keybindings = parse(content);
+        assert_eq!(keybindings.len(), 4);
+        let super_t = Chord::from_keys(&[Key::Super, Key::T]).unwrap();
+        assert_eq!(
+            keybindings[&super_t.on_release().into()].exec,
+            Some(cmd("pactl set-source-mute @DEFAULT_SOURCE@ 1"))
+        );
+        let super_shift_s = Chord::from_keys(&[Key::Super, Key::Shift, Key::S]).unwrap();
+        assert_eq!(
+            keybindings[&super_shift_s.on_release().into()].exec,
+            Some(cmd("maim -s ~/shot.png"))
+        );
+        assert!(!keybindings.contains_key(&super_shift_s.into()));
+
+        let e = Cfg::<&Path>::parse_keybinding("@supr + t").unwrap_err();
+        assert_eq!((e.column, e.token.as_str()), (2, "supr"));
+    }
+
+    #[test]
+    fn parses_chains() {
+        let content = "super + w ; {h,l}
+\tbspc node -f {west,east}
+super + ; ; super + x ; @x
+\tbspc node -c";
+        let keybindings = parse(content);
+        assert_eq!(keybindings.len(), 3);
+        let chain = Chain::new(vec![
+            Chord::from_keys(&[Key::Super, Key::W]).unwrap(),
+            Chord::from_keys(&[Key::L]).unwrap(),
+        ]);
+        assert_eq!(keybindings[&chain].exec, Some(cmd("bspc node -f east")));
+        let chain = Chain::new(vec![
+            Chord::from_keys(&[Key::Super, Key::SemiColon]).unwrap(),
+            Chord::from_keys(&[Key::Super, Key::X]).unwrap(),
+            Chord::from_keys(&[Key::X]).unwrap().on_release(),
+        ]);
+        assert_eq!(keybindings[&chain].exec, Some(cmd("bspc node -c")));
+        assert_eq!(chain.to_string(), "super + ; ; super + x ; @x");
+
+        // only the first chord needs a modifier
+        assert!(Cfg::<&Path>::parse_chain("h ; super + w").is_err());
+        let e = Cfg::<&Path>::parse_chain("super + w ; super + hh").unwrap_err();
+        assert_eq!((e.column, e.token.as_str()), (21, "hh"));
+    }
+
+    #[test]
+    fn parses_modifier_sides() {
+        let keybindings = parse("rctrl + p\n\tpass\nlctrl + rctrl + p\n\tboth\nctrl + p\n\tany");
+        let chord = |modifiers| Chain::from(Chord::new(modifiers, Key::P));
+        assert_eq!(
+            keybindings[&chord(Modifiers::RCTRL)].exec,
+            Some(cmd("pass"))
+        );
+        assert_eq!(
+            keybindings[&chord(Modifiers::LCTRL | Modifiers::RCTRL)].exec,
+            Some(cmd("both"))
+        );
+        assert_eq!(keybindings[&chord(Modifiers::CTRL)].exec, Some(cmd("any")));
+
+        let e = Cfg::<&Path>::parse_keybinding("ctrl + rctrl + p").unwrap_err();
+        assert_eq!((e.column, e.token.as_str()), (8, "rctrl"));
+        assert!(matches!(e.kind, ConfigErrorKind::DuplicateKey));
+    }
+
+    #[test]
+    fn parses_key_numbers() {
+        let keybindings = parse("code:752\n\tg1\nsuper + scan:0xdb\n\tscan\nctrl + code:16\n\tq");
+        let chord = |modifiers, key| Chain::from(Chord::new(modifiers, key));
+        assert_eq!(
+            keybindings[&chord(Modifiers::default(), Key::Code(752))].exec,
+            Some(cmd("g1"))
+        );
+        assert_eq!(
+            keybindings[&chord(Modifiers::SUPER, Key::Scan(0xdb))].exec,
+            Some(cmd("scan"))
+        );
+        assert_eq!(
+            keybindings[&chord(Modifiers::CTRL, Key::Q)].exec,
+            Some(cmd("q"))
+        );
+
+        let e = Cfg::<&Path>::parse_keybinding("super + code:999").unwrap_err();
+        assert_eq!((e.column, e.token.as_str()), (9, "code:999"));
+        assert!(matches!(e.kind, ConfigErrorKind::UnknownKey));
+        // named keys still need modifiers
+        let e = Cfg::<&Path>::parse_keybinding("code:16").unwrap_err();
+        assert!(matches!(e.kind, ConfigErrorKind::InvalidKeybinding));
+    }
+
+    #[test]
+    fn rejects_invalid_keyboard_lines() {
+        let errors = Cfg::new("rkdrc")
+            .parse_str("keyboard layuot=fr\nkeyboard layout=de\nsuper + a\n\techo")
+            .unwrap_err();
+        let summary: Vec<_> = errors.iter().map(|e| (e.line, e.token.as_str())).collect();
+        assert_eq!(
+            summary,
+            vec![(1, "keyboard layuot=fr"), (2, "keyboard layout=de")]
+        );
+        assert!(matches!(
+            errors[0].kind,
+            ConfigErrorKind::InvalidKeyboard(_)
+        ));
+    }
+
+    #[test]
+    #[ignore = "needs libxkbcommon and xkeyboard-config, run with --ignored"]
+    fn resolves_characters_with_keyboard_layout() {
+        let modes = parse_modes(
+            "keyboard layout=fr
+super + a
+\tazerty a
+super + @
+\tat
+super + eacute
+\teacute
+super + pageup
+\tpageup",
+        );
+        let keybindings = &modes[DEFAULT_MODE];
+        let kb = |keys: &[Key]| {
+            keybindings
+                .get(&Chord::from_keys(keys).unwrap().into())
+                .and_then(|action| action.exec.as_ref())
+        };
+        assert_eq!(kb(&[Key::Super, Key::Q]), Some(&cmd("azerty a")));
+        assert_eq!(kb(&[Key::Super, Key::RAlt, Key::Num0]), Some(&cmd("at")));
+        assert_eq!(kb(&[Key::Super, Key::Num2]), Some(&cmd("eacute")));
+        assert_eq!(kb(&[Key::Super, Key::Pageup]), Some(&cmd("pageup")));
+    }
+
+    #[test]
+    fn parses_modes() {
+        let content = "super + r | mode resize
+super + q
+\tbspc node -c
+
+mode resize
+{h,l}
+\tbspc node -z {left -20 0,right 20 0}
+super + q
+\tbspc quit
+esc | mode default
+mode default
+super + x
+\txkill";
+        let modes = parse_modes(content);
+        assert_eq!(modes.len(), 2);
+        let key = |keys: &[Key]| Chain::from(Chord::from_keys(keys).unwrap());
+        let default = &modes[DEFAULT_MODE];
+        assert_eq!(default.len(), 3);
+        let enter = &default[&key(&[Key::Super, Key::R])];
+        assert_eq!(
+            (enter.exec.as_ref(), enter.enter.as_deref()),
+            (None, Some("resize"))
+        );
+        assert_eq!(
+            default[&key(&[Key::Super, Key::X])].exec,
+            Some(cmd("xkill"))
+        );
+
+        let resize = &modes["resize"];
+        assert_eq!(resize.len(), 4);
+        assert_eq!(
+            resize[&key(&[Key::H])].exec,
+            Some(cmd("bspc node -z left -20 0"))
+        );
+        assert_eq!(
+            resize[&key(&[Key::Super, Key::Q])].exec,
+            Some(cmd("bspc quit"))
+        );
+        assert_eq!(
+            resize[&key(&[Key::Esc])].enter.as_deref(),
+            Some(DEFAULT_MODE)
+        );
+
+        let errors = Cfg::new("rkdrc")
+            .parse_str("super + r | mode rezise\nmode resize extra\n\techo\nh\n\techo\nsuper + a\n")
+            .unwrap_err();
+        let summary: Vec<_> = errors
+            .iter()
+            .map(|e| (e.line, e.column, e.token.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (1, 18, "rezise"),
+                (2, 1, "mode resize extra"),
+                (3, 2, "echo"),
+                (4, 1, "h"),
+                (6, 1, "super + a")
+            ]
+        );
+        assert!(matches!(errors[0].kind, ConfigErrorKind::UndefinedMode));
+        assert!(matches!(errors[2].kind, ConfigErrorKind::OrphanCommand));
+    }
+
+    #[test]
+    fn parses_device_sections() {
+        let content = "super + a
+\tglobal
+device name=\"Macro Pad\"
+a
+\tfirefox
+super + a
+\tpad
+mode media
+b
+\tmpc next
+device *
+mode default
+super + b
+\tglobal b
+device id=1d50:615e
+a
+\tother pad";
+        let config = parse_config(content);
+        let key = |keys: &[Key]| Chain::from(Chord::from_keys(keys).unwrap());
+        assert_eq!(config.modes.len(), 1);
+        assert_eq!(config.modes[DEFAULT_MODE].len(), 2);
+        assert_eq!(config.scopes.len(), 2);
+        let pad = &config.scopes[0];
+        assert_eq!(pad.filter.name.as_deref(), Some("Macro Pad"));
+        assert_eq!(pad.modes[DEFAULT_MODE].len(), 2);
+        assert_eq!(
+            pad.modes[DEFAULT_MODE][&key(&[Key::A])].exec,
+            Some(cmd("firefox"))
+        );
+        assert_eq!(
+            pad.modes["media"][&key(&[Key::B])].exec,
+            Some(cmd("mpc next"))
+        );
+        assert_eq!(config.scopes[1].filter.id, Some((0x1d50, 0x615e)));
+        assert!(config.has_mode("media"));
+
+        let keyboard = Keyboard::new("N: Name=\"Macro Pad\"");
+        let layers = config.keybindings(DEFAULT_MODE, &keyboard, &[]);
+        assert_eq!(layers.len(), 2);
+        assert_eq!(
+            layers[0][&key(&[Key::Super, Key::A])].exec,
+            Some(cmd("pad"))
+        );
+        let keyboard = Keyboard::new("N: Name=\"AT Translated Set 2 keyboard\"");
+        assert_eq!(config.keybindings(DEFAULT_MODE, &keyboard, &[]).len(), 1);
+        assert!(config.keybindings("media", &keyboard, &[]).is_empty());
+
+        let errors = Cfg::new("rkdrc")
+            .parse_str("device vendor=1\na\n\techo\ndevice id=1:2\nb\n\techo\nb\n\techo\n")
+            .unwrap_err();
+        let summary: Vec<_> = errors.iter().map(|e| (e.line, e.token.as_str())).collect();
+        assert_eq!(summary, vec![(1, "device vendor=1"), (2, "a"), (7, "b")]);
+        assert!(matches!(errors[0].kind, ConfigErrorKind::InvalidDevice(_)));
+        assert!(matches!(errors[1].kind, ConfigErrorKind::InvalidKeybinding));
+        assert!(matches!(
+            errors[2].kind,
+            ConfigErrorKind::DuplicateKeybinding(5)
+        ));
+    }
 }
@@ -0,0 +1,384 @@
+//! Report of input devices printed by `rkd list-devices`.
+use super::*;
+use std::fmt::{self, Write};
+use std::fs::OpenOptions;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+
+// Name of event files whose device couldn't be queried
+const UNKNOWN_DEVICE_NAME: &str = "unknown device";
+
+/// Whether the current user can open an event file.
+#[derive(Debug, PartialEq)]
+pub enum Access {
+    Readable,
+    // Group owning the file, users usually get access by joining it
+    Denied(Option<String>),
+    Error(String),
+}
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Access::Readable => f.write_str("readable"),
+            Access::Denied(Some(group)) => {
+                write!(f, "permission denied, readable by group {}", group)
+            }
+            Access::Denied(None) => f.write_str("permission denied"),
+            Access::Error(e) => write!(f, "can't be opened - {}", e),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct EventFile {
+    pub path: PathBuf,
+    // Names of links in /dev/input/by-id and /dev/input/by-path
+    pub by_id: Vec<String>,
+    pub by_path: Vec<String>,
+    pub access: Access,
+}
+
+/// A device listed in INPUT_DEVICE_LIST file along with whether rkd listens to it.
+#[derive(Debug, PartialEq)]
+pub struct DeviceReport {
+    pub keyboard: Keyboard,
+    // Why rkd doesn't listen to the device, `None` if it does
+    pub rejection: Option<String>,
+    pub event_files: Vec<EventFile>,
+}
+impl DeviceReport {
+    // Looks up event files of the device and their links in `dev_input`
+    pub fn new(keyboard: Keyboard, dev_input: &Path) -> DeviceReport {
+        let event_files = keyboard
+            .handlers
+            .iter()
+            .filter(|h| h.starts_with(EVENT_HANDLER_PREFIX))
+            .map(|h| {
+                let path = dev_input.join(h);
+                let target = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+                EventFile {
+                    by_id: device_links(&dev_input.join(BY_ID_DIR), &target),
+                    by_path: device_links(&dev_input.join(BY_PATH_DIR), &target),
+                    access: access(&path),
+                    path,
+                }
+            })
+            .collect();
+        DeviceReport {
+            rejection: rejection(&keyboard),
+            keyboard,
+            event_files,
+        }
+    }
+}
+
+// Reports all devices of INPUT_DEVICE_LIST file and event files it doesn't list
+pub fn list_devices() -> io::Result<Vec<DeviceReport>> {
+    let device_list = fs::read_to_string(INPUT_DEVICE_LIST)?;
+    Ok(report_devices(&device_list, Path::new(DEV_INPUT)))
+}
+
+fn report_devices(device_list: &str, dev_input: &Path) -> Vec<DeviceReport> {
+    let keyboards = parse_input_devices(device_list);
+    let unlisted = unlisted_event_files(dev_input, &keyboards);
+    let mut reports: Vec<DeviceReport> = keyboards
+        .into_iter()
+        .map(|keyboard| DeviceReport::new(keyboard, dev_input))
+        .collect();
+    for path in unlisted {
+        let report = match Keyboard::from_device(&path) {
+            Ok(keyboard) => DeviceReport::new(keyboard, dev_input),
+            Err(e) => {
+                let keyboard = Keyboard {
+                    name: UNKNOWN_DEVICE_NAME.to_string(),
+                    handlers: path
+                        .file_name()
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .into_iter()
+                        .collect(),
+                    ..Keyboard::default()
+                };
+                let mut report = DeviceReport::new(keyboard, dev_input);
+                report.rejection = Some(format!(
+                    "it isn't listed in {} and can't be queried - {}",
+                    INPUT_DEVICE_LIST, e
+                ));
+                report
+            }
+        };
+        reports.push(report);
+    }
+    reports
+}
+
+// Explains why rkd doesn't listen to the device, same checks as
+// `read_input_devices` and `DeviceManager::rescan`
+fn rejection(keyboard: &Keyboard) -> Option<String> {
+    if keyboard.name == VIRTUAL_KEYBOARD_NAME {
+        return Some("it's the virtual keyboard of `rkd --grab`".to_string());
+    }
+    if !keyboard
+        .handlers
+        .iter()
+        .any(|h| h.starts_with(EVENT_HANDLER_PREFIX))
+    {
+        return Some("it has no event file".to_string());
+    }
+    if !keyboard.ev.has(KEY_EV) {
+        return Some("it reports no keys".to_string());
+    }
+    if keyboard.is_keyboard() || keyboard.has_media_keys() {
+        return None;
+    }
+    let missing: Vec<Key> = (1..32)
+        .filter(|code| !keyboard.key.has(*code))
+        .map(Key::from_code)
+        .collect();
+    Some(format!(
+        "it reports no media keys and misses {} of the keys from esc to s, like '{}'",
+        missing.len(),
+        missing[0]
+    ))
+}
+
+fn access(path: &Path) -> Access {
+    let opened = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path);
+    match opened {
+        Ok(_) => Access::Readable,
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => Access::Denied(
+            fs::metadata(path)
+                .ok()
+                .and_then(|metadata| group_name(metadata.gid())),
+        ),
+        Err(e) => Access::Error(e.to_string()),
+    }
+}
+
+fn group_name(gid: u32) -> Option<String> {
+    let group = unsafe { libc::getgrgid(gid) };
+    if group.is_null() {
+        return None;
+    }
+    let name = unsafe { std::ffi::CStr::from_ptr((*group).gr_name) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+fn bus(id: InputId) -> String {
+    id.bus_name()
+        .map(String::from)
+        .unwrap_or_else(|| format!("{:04x}", id.bus))
+}
+
+// Formats reports as blocks of `name: value` lines separated by empty lines
+pub fn format_text(reports: &[DeviceReport]) -> String {
+    let mut out = String::new();
+    for (i, report) in reports.iter().enumerate() {
+        let k = &report.keyboard;
+        if i > 0 {
+            out.push('\n');
+        }
+        let _ = writeln!(out, "{}", k.name);
+        let keyboard = match &report.rejection {
+            None => "yes".to_string(),
+            Some(reason) => format!("no - {}", reason),
+        };
+        let _ = writeln!(out, "  keyboard: {}", keyboard);
+        let _ = writeln!(
+            out,
+            "  id:       {} {:04x}:{:04x} version {:04x}",
+            bus(k.id),
+            k.id.vendor,
+            k.id.product,
+            k.id.version
+        );
+        for (name, value) in [("phys", &k.phys), ("uniq", &k.uniq), ("sysfs", &k.sysfs)] {
+            if !value.is_empty() {
+                let _ = writeln!(out, "  {:<9} {}", format!("{}:", name), value);
+            }
+        }
+        for event_file in &report.event_files {
+            let _ = writeln!(
+                out,
+                "  event:    {} - {}",
+                event_file.path.display(),
+                event_file.access
+            );
+            for link in &event_file.by_id {
+                let _ = writeln!(out, "  by-id:    {}", link);
+            }
+            for link in &event_file.by_path {
+                let _ = writeln!(out, "  by-path:  {}", link);
+            }
+        }
+    }
+    out
+}
+
+// Formats reports as a JSON array with an object for each device
+pub fn format_json(reports: &[DeviceReport]) -> String {
+    let strings = |values: &[String]| {
+        let values: Vec<String> = values.iter().map(|v| json_string(v)).collect();
+        format!("[{}]", values.join(","))
+    };
+    let devices: Vec<String> = reports
+        .iter()
+        .map(|report| {
+            let k = &report.keyboard;
+            let event_files: Vec<String> = report
+                .event_files
+                .iter()
+                .map(|event_file| {
+                    format!(
+                        "{{\"path\":{},\"by_id\":{},\"by_path\":{},\"readable\":{},\"access\":{}}}",
+                        json_string(&event_file.path.to_string_lossy()),
+                        strings(&event_file.by_id),
+                        strings(&event_file.by_path),
+                        event_file.access == Access::Readable,
+                        json_string(&event_file.access.to_string())
+                    )
+                })
+                .collect();
+            format!(
+                "  {{\"name\":{},\"keyboard\":{},\"reason\":{},\"bus\":{},\"vendor\":\"{:04x}\",\"product\":\"{:04x}\",\"version\":\"{:04x}\",\"phys\":{},\"uniq\":{},\"sysfs\":{},\"event_files\":[{}]}}",
+                json_string(&k.name),
+                report.rejection.is_none(),
+                report
+                    .rejection
+                    .as_deref()
+                    .map(json_string)
+                    .unwrap_or_else(|| "null".to_string()),
+                json_string(&bus(k.id)),
+                k.id.vendor,
+                k.id.product,
+                k.id.version,
+                json_string(&k.phys),
+                json_string(&k.uniq),
+                json_string(&k.sysfs),
+                event_files.join(",")
+            )
+        })
+        .collect();
+    if devices.is_empty() {
+        return "[]\n".to_string();
+    }
+    format!("[\n{}\n]\n", devices.join(",\n"))
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", ch as u32);
+            }
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explains_rejected_devices() {
+        let reports: Vec<DeviceReport> =
+            parse_input_devices(include_str!("../fixtures/devices_vm"))
+                .into_iter()
+                .map(|k| DeviceReport::new(k, Path::new("/nonexistent")))
+                .collect();
+        let rejections: Vec<_> = reports.iter().map(|r| r.rejection.as_deref()).collect();
+        assert_eq!(
+            rejections,
+            vec![
+                Some(
+                    "it reports no media keys and misses 31 of the keys from esc to s, like 'esc'"
+                ),
+                None,
+                Some(
+                    "it reports no media keys and misses 31 of the keys from esc to s, like 'esc'"
+                ),
+                Some(
+                    "it reports no media keys and misses 31 of the keys from esc to s, like 'esc'"
+                ),
+                Some("it reports no keys"),
+            ]
+        );
+        assert!(matches!(reports[1].event_files[0].access, Access::Error(_)));
+        let virtual_keyboard = Keyboard {
+            name: VIRTUAL_KEYBOARD_NAME.to_string(),
+            ..Keyboard::default()
+        };
+        assert!(rejection(&virtual_keyboard).unwrap().contains("--grab"));
+        assert_eq!(
+            rejection(&Keyboard::default()).as_deref(),
+            Some("it has no event file")
+        );
+    }
+
+    #[test]
+    fn formats_reports() {
+        let dev_input = std::env::temp_dir().join(format!("rkd-devices-{}", std::process::id()));
+        fs::create_dir_all(dev_input.join(BY_PATH_DIR)).unwrap();
+        fs::write(dev_input.join("event1"), "").unwrap();
+        std::os::unix::fs::symlink(
+            "../event1",
+            dev_input
+                .join(BY_PATH_DIR)
+                .join("platform-i8042-serio-0-event-kbd"),
+        )
+        .unwrap();
+        fs::write(dev_input.join("event12"), "").unwrap();
+        let mut reports = report_devices(include_str!("../fixtures/devices_vm"), &dev_input);
+        fs::remove_dir_all(&dev_input).unwrap();
+
+        // event12 isn't listed and, being a regular file, can't be queried
+        let unlisted = reports.pop().unwrap();
+        assert_eq!(unlisted.keyboard.name, UNKNOWN_DEVICE_NAME);
+        assert_eq!(unlisted.event_files[0].path, dev_input.join("event12"));
+        assert!(unlisted
+            .rejection
+            .unwrap()
+            .starts_with("it isn't listed in /proc/bus/input/devices and can't be queried"));
+        let reports = &reports[1..2];
+
+        let event1 = dev_input.join("event1");
+        assert_eq!(
+            format_text(reports),
+            format!(
+                "AT Translated Set 2 keyboard
+  keyboard: yes
+  id:       i8042 0001:0001 version ab41
+  phys:     isa0060/serio0/input0
+  sysfs:    /devices/platform/i8042/serio0/input/input1
+  event:    {} - readable
+  by-path:  platform-i8042-serio-0-event-kbd
+",
+                event1.display()
+            )
+        );
+        assert_eq!(
+            format_json(reports),
+            format!(
+                "[
+  {{\"name\":\"AT Translated Set 2 keyboard\",\"keyboard\":true,\"reason\":null,\"bus\":\"i8042\",\"vendor\":\"0001\",\"product\":\"0001\",\"version\":\"ab41\",\"phys\":\"isa0060/serio0/input0\",\"uniq\":\"\",\"sysfs\":\"/devices/platform/i8042/serio0/input/input1\",\"event_files\":[{{\"path\":\"{}\",\"by_id\":[],\"by_path\":[\"platform-i8042-serio-0-event-kbd\"],\"readable\":true,\"access\":\"readable\"}}]}}
+]
+",
+                event1.display()
+            )
+        );
+        assert_eq!(
+            json_string("a \"b\"\\\n\u{1}"),
+            "\"a \\\"b\\\"\\\\\\n\\u0001\""
+        );
+    }
+}
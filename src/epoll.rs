@@ -0,0 +1,145 @@
+//! Minimal wrapper around epoll used to wait on all input devices at once.
+use super::*;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+
+const MAX_EVENTS: usize = 32;
+
+#[derive(Debug)]
+pub struct Epoll {
+    file: File,
+}
+impl Epoll {
+    pub fn new() -> io::Result<Epoll> {
+        let fd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Epoll {
+            file: unsafe { File::from_raw_fd(fd) },
+        })
+    }
+    // Registers `fd` for reading, `token` is returned by `wait` once it's readable
+    pub fn add(&self, fd: RawFd, token: u64) -> io::Result<()> {
+        let mut event = libc::epoll_event {
+            events: libc::EPOLLIN as u32,
+            u64: token,
+        };
+        self.ctl(libc::EPOLL_CTL_ADD, fd, &mut event)
+    }
+    pub fn delete(&self, fd: RawFd) -> io::Result<()> {
+        let mut event = libc::epoll_event { events: 0, u64: 0 };
+        self.ctl(libc::EPOLL_CTL_DEL, fd, &mut event)
+    }
+    fn ctl(&self, op: libc::c_int, fd: RawFd, event: &mut libc::epoll_event) -> io::Result<()> {
+        if unsafe { libc::epoll_ctl(self.file.as_raw_fd(), op, fd, event) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+    // Blocks until one of the registered fds is readable or the timeout passes
+    // and returns tokens of all readable fds. No timeout waits indefinitely.
+    pub fn wait(&self, timeout: Option<Duration>) -> io::Result<Vec<u64>> {
+        let mut events = [libc::epoll_event { events: 0, u64: 0 }; MAX_EVENTS];
+        let timeout = timeout.map_or(-1, |t| {
+            // rounds up so that the loop doesn't wake up right before a deadline
+            let ms = t.as_micros().div_ceil(1000);
+            ms.min(libc::c_int::MAX as u128) as libc::c_int
+        });
+        let n = unsafe {
+            libc::epoll_wait(
+                self.file.as_raw_fd(),
+                events.as_mut_ptr(),
+                MAX_EVENTS as libc::c_int,
+                timeout,
+            )
+        };
+        if n < 0 {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::Interrupted {
+                return Ok(Vec::new());
+            }
+            return Err(e);
+        }
+        Ok(events[..n as usize].iter().map(|e| e.u64).collect())
+    }
+}
+
+/// Wakes up an epoll loop from other threads, backed by an eventfd.
+#[derive(Clone, Debug)]
+pub struct Notifier {
+    file: Arc<File>,
+}
+impl Notifier {
+    pub fn new() -> io::Result<Notifier> {
+        let fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Notifier {
+            file: Arc::new(unsafe { File::from_raw_fd(fd) }),
+        })
+    }
+    // Makes the fd readable until `clear` is called
+    pub fn notify(&self) -> io::Result<()> {
+        io::Write::write_all(&mut &*self.file, &1u64.to_ne_bytes())
+    }
+    pub fn clear(&self) -> io::Result<()> {
+        let mut count = [0u8; 8];
+        match (&*self.file).read(&mut count) {
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+impl AsRawFd for Notifier {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waits_for_readable_fds() {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let (rx, mut tx) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+        let epoll = Epoll::new().unwrap();
+        epoll.add(rx.as_raw_fd(), 7).unwrap();
+        assert!(epoll
+            .wait(Some(Duration::from_millis(1)))
+            .unwrap()
+            .is_empty());
+
+        io::Write::write_all(&mut tx, b"x").unwrap();
+        assert_eq!(epoll.wait(None).unwrap(), vec![7]);
+
+        epoll.delete(rx.as_raw_fd()).unwrap();
+        assert!(epoll
+            .wait(Some(Duration::from_millis(1)))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn wakes_up_on_notify() {
+        let notifier = Notifier::new().unwrap();
+        let epoll = Epoll::new().unwrap();
+        epoll.add(notifier.as_raw_fd(), 3).unwrap();
+        let other = notifier.clone();
+        thread::spawn(move || other.notify().unwrap())
+            .join()
+            .unwrap();
+        assert_eq!(epoll.wait(None).unwrap(), vec![3]);
+        notifier.clear().unwrap();
+        assert!(epoll
+            .wait(Some(Duration::from_millis(1)))
+            .unwrap()
+            .is_empty());
+        // clearing without a notification doesn't block
+        notifier.clear().unwrap();
+    }
+}
@@ -0,0 +1,180 @@
+use super::*;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum ConfigErrorKind {
+    Io(io::Error),
+    UnknownKey,
+    DuplicateKey,
+    InvalidKeybinding,
+    DuplicateKeybinding(usize),
+    UnreachableKeybinding,
+    ShadowedKeybinding(String, usize),
+    MissingCommand,
+    OrphanCommand,
+    InvalidSequence(String),
+    InvalidCommand(String),
+    InvalidOption(String),
+    InvalidMode,
+    UndefinedMode,
+    InvalidKeyboard(String),
+    InvalidDevice(String),
+}
+
+/// A single problem found while parsing a config file.
+///
+/// `line` and `column` are 1-based, `line` is 0 if the error is not related
+/// to any particular line of the file.
+#[derive(Debug)]
+pub struct ConfigError {
+    pub kind: ConfigErrorKind,
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub token: String,
+    pub source_line: String,
+    pub suggestion: Option<String>,
+}
+impl ConfigError {
+    pub fn new<S: Into<String>>(kind: ConfigErrorKind, token: S) -> ConfigError {
+        ConfigError {
+            kind,
+            file: PathBuf::new(),
+            line: 0,
+            column: 1,
+            token: token.into(),
+            source_line: String::new(),
+            suggestion: None,
+        }
+    }
+    pub fn column(mut self, column: usize) -> Self {
+        self.column = column;
+        self
+    }
+    pub fn suggestion(mut self, suggestion: Option<String>) -> Self {
+        self.suggestion = suggestion;
+        self
+    }
+    // Sets the location of the error, the source line is used to underline the token
+    pub fn location<P: AsRef<Path>>(mut self, file: P, line: usize, source_line: &str) -> Self {
+        self.file = file.as_ref().to_path_buf();
+        self.line = line;
+        self.source_line = source_line.to_string();
+        self
+    }
+    pub fn message(&self) -> String {
+        use self::ConfigErrorKind::*;
+        match &self.kind {
+            Io(e) => format!("failed to read config file - {}", e),
+            UnknownKey => format!("unknown key '{}'", self.token),
+            DuplicateKey => format!(
+                "'{}' duplicates a key already present in the keybinding",
+                self.token
+            ),
+            InvalidKeybinding => format!(
+                "invalid keybinding '{}' - expected modifiers followed by a single key, or a single media or numbered key",
+                self.token
+            ),
+            DuplicateKeybinding(line) => format!(
+                "keybinding '{}' is already defined at line {}",
+                self.token, line
+            ),
+            UnreachableKeybinding => format!(
+                "keybinding '{}' can never be triggered - no key code maps to one of its keys",
+                self.token
+            ),
+            ShadowedKeybinding(chain, line) => format!(
+                "keybinding '{}' can never be triggered - '{}' at line {} runs as soon as its first chords are pressed",
+                self.token, chain, line
+            ),
+            MissingCommand => format!("keybinding '{}' has no command", self.token),
+            OrphanCommand => format!("command '{}' without a keybinding", self.token),
+            InvalidSequence(e) => format!("invalid sequence in '{}' - {}", self.token, e),
+            InvalidCommand(e) => format!("invalid command '{}' - {}", self.token, e),
+            InvalidOption(e) => format!("invalid options '{}' - {}", self.token, e),
+            InvalidMode => format!("invalid mode '{}' - expected 'mode <name>'", self.token),
+            UndefinedMode => format!("mode '{}' has no keybindings", self.token),
+            InvalidKeyboard(e) => format!("invalid keyboard '{}' - {}", self.token, e),
+            InvalidDevice(e) => format!("invalid device section '{}' - {}", self.token, e),
+        }
+    }
+}
+impl fmt::Display for ConfigError {
+    // Formats the error in a compiler like fashion:
+    //
+    // error: unknown key 'supr'
+    //  --> rkdrc:3:1
+    //   |
+    // 3 | supr + q
+    //   | ^^^^
+    //   = help: did you mean 'super'?
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "error: {}", self.message())?;
+        if self.line == 0 {
+            return write!(f, " --> {}", self.file.display());
+        }
+        let line_no = self.line.to_string();
+        let pad = " ".repeat(line_no.len());
+        write!(
+            f,
+            "{}--> {}:{}:{}",
+            pad,
+            self.file.display(),
+            self.line,
+            self.column
+        )?;
+        if !self.source_line.is_empty() {
+            let underline_start = self
+                .source_line
+                .chars()
+                .take(self.column.saturating_sub(1))
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect::<String>();
+            let underline = "^".repeat(self.token.chars().count().max(1));
+            write!(
+                f,
+                "\n{} |\n{} | {}\n{} | {}{}",
+                pad, line_no, self.source_line, pad, underline_start, underline
+            )?;
+        }
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, "\n{} = help: did you mean '{}'?", pad, suggestion)?;
+        }
+        Ok(())
+    }
+}
+impl Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_errors() {
+        let e = ConfigError::new(ConfigErrorKind::UnknownKey, "supr")
+            .column(1)
+            .suggestion(Some("super".to_string()))
+            .location("rkdrc", 3, "supr + q");
+        assert_eq!(
+            e.to_string(),
+            "error: unknown key 'supr'
+ --> rkdrc:3:1
+  |
+3 | supr + q
+  | ^^^^
+  = help: did you mean 'super'?"
+        );
+        let e = ConfigError::new(ConfigErrorKind::DuplicateKey, "shift")
+            .column(9)
+            .location("rkdrc", 12, "super + shift + Q");
+        assert_eq!(
+            e.to_string(),
+            "error: 'shift' duplicates a key already present in the keybinding
+  --> rkdrc:12:9
+   |
+12 | super + shift + Q
+   |         ^^^^^"
+        );
+    }
+}
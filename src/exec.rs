@@ -0,0 +1,219 @@
+use super::*;
+use std::env;
+
+const DEFAULT_SHELL: &str = "/bin/sh";
+const RKD_SHELL_ENV: &str = "RKD_SHELL";
+const SHELL_ENV: &str = "SHELL";
+
+/// Describes how the command of a keybinding is started.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExecMode {
+    /// Pass the whole command line to `shell -c`
+    Shell(String),
+    /// Split the command line into words and execute the first one directly
+    Direct,
+}
+impl Default for ExecMode {
+    // Uses $RKD_SHELL, $SHELL or /bin/sh in that order
+    fn default() -> Self {
+        let shell = env::var(RKD_SHELL_ENV)
+            .or_else(|_| env::var(SHELL_ENV))
+            .ok()
+            .filter(|shell| !shell.is_empty())
+            .unwrap_or_else(|| DEFAULT_SHELL.to_string());
+        ExecMode::Shell(shell)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Exec {
+    program: String,
+    args: Vec<String>,
+}
+impl Exec {
+    pub fn new<S: AsRef<str>>(cmd: S) -> Result<Exec, String> {
+        Self::with_mode(cmd, &ExecMode::default())
+    }
+    pub fn with_mode<S: AsRef<str>>(cmd: S, mode: &ExecMode) -> Result<Exec, String> {
+        trace!(
+            "Creating exec instance from '{}' - {:?}",
+            cmd.as_ref(),
+            mode
+        );
+        let cmd = cmd.as_ref().trim();
+        if cmd.is_empty() {
+            return Err("empty command".to_string());
+        }
+        match mode {
+            ExecMode::Shell(shell) => Ok(Exec {
+                program: shell.clone(),
+                args: vec!["-c".to_string(), cmd.to_string()],
+            }),
+            ExecMode::Direct => match split_words(cmd) {
+                Some(mut words) if !words.is_empty() => Ok(Exec {
+                    program: words.remove(0),
+                    args: words,
+                }),
+                Some(_) => Err("empty command".to_string()),
+                None => Err("unterminated quote".to_string()),
+            },
+        }
+    }
+    pub fn run(&self) -> io::Result<Child> {
+        self.run_with_env(&[])
+    }
+    // Runs the command with additional environment variables overriding rkd's own
+    pub fn run_with_env(&self, env: &[(String, String)]) -> io::Result<Child> {
+        Command::new(&self.program)
+            .args(&self.args)
+            .envs(env.iter().map(|(name, value)| (name, value)))
+            .spawn()
+    }
+    // Runs the command without waiting for it, a thread waits for it to exit so it
+    // doesn't stay around as a zombie. Returns the pid of the command.
+    pub fn spawn_reaped(&self, env: &[(String, String)]) -> io::Result<u32> {
+        let mut child = self.run_with_env(env)?;
+        let pid = child.id();
+        thread::spawn(move || {
+            if let Err(e) = child.wait() {
+                error!("failed to wait for command {} - {}", pid, e);
+            }
+        });
+        Ok(pid)
+    }
+}
+
+// Splits a command line into words following POSIX shell quoting rules:
+// single quotes preserve everything literally, double quotes allow escaping
+// of `"`, `\`, `$` and `` ` `` and a backslash outside of quotes escapes
+// the next character. Returns None on unterminated quotes.
+pub fn split_words(line: &str) -> Option<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = line.chars();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            ' ' | '\t' | '\n' => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next()? {
+                        '\'' => break,
+                        c => word.push(c),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '\\' => match chars.next()? {
+                            c @ ('"' | '\\' | '$' | '`') => word.push(c),
+                            '\n' => {}
+                            c => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                        },
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                match chars.next() {
+                    Some('\n') => {}
+                    Some(c) => word.push(c),
+                    None => word.push('\\'),
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+
+    Some(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(v: &[&str]) -> Option<Vec<String>> {
+        Some(v.iter().map(|w| w.to_string()).collect())
+    }
+
+    #[test]
+    fn splits_words() {
+        let table = vec![
+            ("bspc node -c", words(&["bspc", "node", "-c"])),
+            ("  bspc   node\t-c ", words(&["bspc", "node", "-c"])),
+            ("echo 'a b' c", words(&["echo", "a b", "c"])),
+            ("echo \"a 'b' \\\"c\\\"\"", words(&["echo", "a 'b' \"c\""])),
+            ("echo \"\\n\"", words(&["echo", "\\n"])),
+            ("echo a\\ b", words(&["echo", "a b"])),
+            ("echo '' \"\"", words(&["echo", "", ""])),
+            ("echo a'b'\"c\"", words(&["echo", "abc"])),
+            ("echo '$HOME' && ls", words(&["echo", "$HOME", "&&", "ls"])),
+            ("echo 'abc", None),
+            ("echo \"abc", None),
+            ("", words(&[])),
+        ];
+        for (line, expected) in table {
+            println!("splitting {}", line);
+            assert_eq!(split_words(line), expected);
+        }
+    }
+
+    #[test]
+    fn reaps_commands() {
+        let exec = Exec::with_mode("true", &ExecMode::Direct).unwrap();
+        let pid = exec.spawn_reaped(&[]).unwrap();
+        // a zombie keeps its /proc entry until it's waited for
+        let stat = format!("/proc/{}", pid);
+        for _ in 0..100 {
+            if !Path::new(&stat).exists() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("command {} wasn't reaped", pid);
+    }
+
+    #[test]
+    fn builds_execs() {
+        let shell = ExecMode::Shell("/bin/bash".to_string());
+        assert_eq!(
+            Exec::with_mode("bspc desktop -f ^1 && bspc desktop -f ^4", &shell),
+            Ok(Exec {
+                program: "/bin/bash".to_string(),
+                args: vec![
+                    "-c".to_string(),
+                    "bspc desktop -f ^1 && bspc desktop -f ^4".to_string()
+                ],
+            })
+        );
+        assert_eq!(
+            Exec::with_mode("notify-send 'hello world'", &ExecMode::Direct),
+            Ok(Exec {
+                program: "notify-send".to_string(),
+                args: vec!["hello world".to_string()],
+            })
+        );
+        assert!(Exec::with_mode("   ", &shell).is_err());
+        assert!(Exec::with_mode("'", &ExecMode::Direct).is_err());
+    }
+}
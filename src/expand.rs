@@ -0,0 +1,227 @@
+//! sxhkd style sequence expansion of keybindings and commands.
+//!
+//! A keybinding like `super + {_,shift + }{1-3}` expands to six keybindings,
+//! the n-th element of each sequence in the keybinding is paired with the n-th
+//! element of the corresponding sequence in its command.
+const EMPTY_ELEMENT: &str = "_";
+
+// A brace sequence found in a line, `prefix` is everything before it
+// and `suffix` everything after it.
+#[derive(Debug, PartialEq)]
+struct Sequence<'a> {
+    prefix: &'a str,
+    elements: Vec<String>,
+    suffix: &'a str,
+}
+impl<'a> Sequence<'a> {
+    fn substitute(&self, i: usize) -> String {
+        format!("{}{}{}", self.prefix, self.elements[i], self.suffix)
+    }
+}
+
+/// Expands all sequences of a keybinding and its command into pairs of
+/// keybinding and command lines. If the keybinding contains no sequences
+/// both lines are returned untouched.
+pub fn expand(keybinding: &str, cmd: &str) -> Result<Vec<(String, String)>, String> {
+    if first_sequence(keybinding)?.is_none() {
+        return Ok(vec![(keybinding.to_string(), cmd.to_string())]);
+    }
+    expand_sequences(keybinding, cmd)
+}
+
+fn expand_sequences(keybinding: &str, cmd: &str) -> Result<Vec<(String, String)>, String> {
+    let kb_seq = match first_sequence(keybinding)? {
+        Some(seq) => seq,
+        None => return Ok(vec![(unescape(keybinding), unescape(cmd))]),
+    };
+    let commands: Vec<String> = match first_sequence(cmd)? {
+        Some(cmd_seq) => {
+            if cmd_seq.elements.len() != kb_seq.elements.len() {
+                return Err(format!(
+                    "sequence lengths differ - keybinding has {} elements, command has {}",
+                    kb_seq.elements.len(),
+                    cmd_seq.elements.len()
+                ));
+            }
+            (0..cmd_seq.elements.len())
+                .map(|i| cmd_seq.substitute(i))
+                .collect()
+        }
+        None => vec![cmd.to_string(); kb_seq.elements.len()],
+    };
+
+    let mut expanded = Vec::new();
+    for (i, cmd) in commands.iter().enumerate() {
+        expanded.extend(expand_sequences(&kb_seq.substitute(i), cmd)?);
+    }
+    Ok(expanded)
+}
+
+// Finds the first unescaped brace sequence in the line
+fn first_sequence(line: &str) -> Result<Option<Sequence<'_>>, String> {
+    let mut start = None;
+    let mut escaped = false;
+    for (i, ch) in line.char_indices() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '{' if start.is_some() => return Err("nested sequences are not supported".into()),
+            '{' => start = Some(i),
+            // a closing brace without an opening one is taken literally
+            '}' => {
+                if let Some(start) = start {
+                    return Ok(Some(Sequence {
+                        prefix: &line[..start],
+                        elements: parse_elements(&line[start + 1..i])?,
+                        suffix: &line[i + 1..],
+                    }));
+                }
+            }
+            _ => {}
+        }
+    }
+    match start {
+        Some(_) => Err("unmatched '{'".into()),
+        None => Ok(None),
+    }
+}
+
+// Splits the content of a sequence on unescaped commas and expands ranges like `a-z` or `1-9`
+fn parse_elements(content: &str) -> Result<Vec<String>, String> {
+    let mut raw_elements = Vec::new();
+    let mut element = String::new();
+    let mut escaped = false;
+    for ch in content.chars() {
+        match ch {
+            _ if escaped => {
+                escaped = false;
+                element.push(ch);
+            }
+            '\\' => {
+                escaped = true;
+                element.push(ch);
+            }
+            ',' => raw_elements.push(std::mem::take(&mut element)),
+            _ => element.push(ch),
+        }
+    }
+    raw_elements.push(element);
+
+    let mut elements = Vec::new();
+    for element in raw_elements {
+        if element.trim() == EMPTY_ELEMENT {
+            elements.push(String::new());
+        } else if let Some((from, to)) = parse_range(&element) {
+            if from > to {
+                return Err(format!("invalid range '{}'", element));
+            }
+            elements.extend((from..=to).map(|c| c.to_string()));
+        } else {
+            elements.push(element);
+        }
+    }
+    Ok(elements)
+}
+
+fn parse_range(element: &str) -> Option<(char, char)> {
+    let chars: Vec<char> = element.trim().chars().collect();
+    match chars.as_slice() {
+        [from, '-', to]
+            if (from.is_ascii_digit() && to.is_ascii_digit())
+                || (from.is_ascii_lowercase() && to.is_ascii_lowercase())
+                || (from.is_ascii_uppercase() && to.is_ascii_uppercase()) =>
+        {
+            Some((*from, *to))
+        }
+        _ => None,
+    }
+}
+
+// Removes backslashes escaping braces, commas and the empty element marker
+fn unescape(line: &str) -> String {
+    let mut unescaped = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match (ch, chars.peek()) {
+            ('\\', Some('{' | '}' | ',' | '_')) => {}
+            _ => unescaped.push(ch),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(v: &[(&str, &str)]) -> Result<Vec<(String, String)>, String> {
+        Ok(v.iter()
+            .map(|(kb, cmd)| (kb.to_string(), cmd.to_string()))
+            .collect())
+    }
+
+    #[test]
+    fn expands_sequences() {
+        let table = vec![
+            (
+                ("super + {1-3}", "bspc desktop -f ^{1-3}"),
+                pairs(&[
+                    ("super + 1", "bspc desktop -f ^1"),
+                    ("super + 2", "bspc desktop -f ^2"),
+                    ("super + 3", "bspc desktop -f ^3"),
+                ]),
+            ),
+            (
+                ("super + {_,shift + }{h,l}", "bspc node -{f,s} {west,east}"),
+                pairs(&[
+                    ("super + h", "bspc node -f west"),
+                    ("super + l", "bspc node -f east"),
+                    ("super + shift + h", "bspc node -s west"),
+                    ("super + shift + l", "bspc node -s east"),
+                ]),
+            ),
+            (
+                ("super + {a-c,x}", "rofi"),
+                pairs(&[
+                    ("super + a", "rofi"),
+                    ("super + b", "rofi"),
+                    ("super + c", "rofi"),
+                    ("super + x", "rofi"),
+                ]),
+            ),
+            (
+                (
+                    "XF86Audio{Raise,Lower}Volume",
+                    "pactl set-sink-volume 0 {+,-}5%",
+                ),
+                pairs(&[
+                    ("XF86AudioRaiseVolume", "pactl set-sink-volume 0 +5%"),
+                    ("XF86AudioLowerVolume", "pactl set-sink-volume 0 -5%"),
+                ]),
+            ),
+            (
+                ("super + {q,w}", "awk '\\{print $1\\}' {a,b}"),
+                pairs(&[
+                    ("super + q", "awk '{print $1}' a"),
+                    ("super + w", "awk '{print $1}' b"),
+                ]),
+            ),
+            (
+                ("super + q", "echo ${HOME} {a,b}"),
+                pairs(&[("super + q", "echo ${HOME} {a,b}")]),
+            ),
+        ];
+        for ((kb, cmd), expected) in table {
+            println!("expanding {} - {}", kb, cmd);
+            assert_eq!(expand(kb, cmd), expected);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_sequences() {
+        assert!(expand("super + {1-3}", "echo {a,b}").is_err());
+        assert!(expand("super + {1,2", "echo").is_err());
+        assert!(expand("super + {1,{2,3}}", "echo").is_err());
+        assert!(expand("super + {9-1}", "echo").is_err());
+    }
+}
@@ -0,0 +1,89 @@
+//! Minimal wrapper around inotify used to watch the config file and `/dev/input`.
+use super::*;
+use std::ffi::{CString, OsStr, OsString};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+
+const SIZE_OF_INOTIFY_EVENT: usize = mem::size_of::<libc::inotify_event>();
+const EVENT_BUFFER_SIZE: usize = 4096;
+
+#[derive(Debug)]
+pub struct Inotify {
+    file: File,
+}
+impl Inotify {
+    pub fn new() -> io::Result<Inotify> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Inotify {
+            file: unsafe { File::from_raw_fd(fd) },
+        })
+    }
+    pub fn add_watch(&self, path: &Path, mask: u32) -> io::Result<()> {
+        let path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        if unsafe { libc::inotify_add_watch(self.file.as_raw_fd(), path.as_ptr(), mask) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+    // Blocks until at least one event is available and returns names of files
+    // the events refer to
+    pub fn read_names(&mut self) -> io::Result<Vec<OsString>> {
+        let mut buf = [0u8; EVENT_BUFFER_SIZE];
+        let n = self.file.read(&mut buf)?;
+        Ok(changed_files(&buf[..n])
+            .map(|name| OsStr::from_bytes(name).to_os_string())
+            .collect())
+    }
+}
+impl AsRawFd for Inotify {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+// Iterates over names of files from a buffer of inotify events
+pub fn changed_files(buf: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        if offset + SIZE_OF_INOTIFY_EVENT > buf.len() {
+            return None;
+        }
+        let event: libc::inotify_event =
+            unsafe { std::ptr::read_unaligned(buf[offset..].as_ptr() as *const _) };
+        let name_start = offset + SIZE_OF_INOTIFY_EVENT;
+        let name_end = (name_start + event.len as usize).min(buf.len());
+        offset = name_end;
+        let name = &buf[name_start..name_end];
+        let len = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+        Some(&name[..len])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_changed_file_names() {
+        let mut buf = Vec::new();
+        for name in &["rkdrc", "other.swp"] {
+            let mut padded = name.as_bytes().to_vec();
+            padded.resize(16, 0);
+            let event = libc::inotify_event {
+                wd: 1,
+                mask: libc::IN_CLOSE_WRITE,
+                cookie: 0,
+                len: padded.len() as u32,
+            };
+            let raw: [u8; SIZE_OF_INOTIFY_EVENT] = unsafe { mem::transmute(event) };
+            buf.extend_from_slice(&raw);
+            buf.extend_from_slice(&padded);
+        }
+        let names: Vec<_> = changed_files(&buf).collect();
+        assert_eq!(names, vec![&b"rkdrc"[..], &b"other.swp"[..]]);
+    }
+}
@@ -1,8 +1,109 @@
 use super::*;
+use std::os::unix::io::AsRawFd;
 
-#[derive(Debug, PartialEq)]
+/// Bitmap of capabilities as listed in `B:` lines of INPUT_DEVICE_LIST file
+/// (`B: KEY=1000000000007 ff9f207ac14057ff ...`). Words are listed from the
+/// most significant one and each of them is as wide as the kernel's `long`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Capabilities {
+    words: Vec<u64>,
+}
+impl Capabilities {
+    pub fn parse(bitmap: &str) -> Option<Capabilities> {
+        let mut words = bitmap
+            .split_whitespace()
+            .map(|word| u64::from_str_radix(word, 16).ok())
+            .collect::<Option<Vec<u64>>>()?;
+        words.reverse();
+        Some(Capabilities { words })
+    }
+    // Builds the bitmap from the numbers of its set bits
+    pub fn from_bits(bits: &[u16]) -> Capabilities {
+        let mut words = Vec::new();
+        for bit in bits.iter().map(|bit| *bit as usize) {
+            if words.len() <= bit / BITS_PER_LONG {
+                words.resize(bit / BITS_PER_LONG + 1, 0);
+            }
+            words[bit / BITS_PER_LONG] |= 1 << (bit % BITS_PER_LONG);
+        }
+        Capabilities { words }
+    }
+    pub fn has(&self, bit: u16) -> bool {
+        let bit = bit as usize;
+        self.words
+            .get(bit / BITS_PER_LONG)
+            .map(|word| word >> (bit % BITS_PER_LONG) & 1 == 1)
+            .unwrap_or(false)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|word| *word == 0)
+    }
+}
+
+/// Bus and IDs of a device, as listed in `I:` lines of INPUT_DEVICE_LIST file
+/// and returned by EVIOCGID in `struct input_id`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct InputId {
+    pub bus: u16,
+    pub vendor: u16,
+    pub product: u16,
+    pub version: u16,
+}
+impl InputId {
+    // Parses `Bus=0003 Vendor=046d Product=c33a Version=0111`
+    pub fn parse(line: &str) -> Option<InputId> {
+        let mut id = InputId::default();
+        for (field, value) in line.split_whitespace().filter_map(|f| f.split_once('=')) {
+            let value = u16::from_str_radix(value, 16).ok()?;
+            match field {
+                "Bus" => id.bus = value,
+                "Vendor" => id.vendor = value,
+                "Product" => id.product = value,
+                "Version" => id.version = value,
+                _ => {}
+            }
+        }
+        Some(id)
+    }
+    // Name of the bus the device is connected through, see BUS_* of linux/input.h
+    pub fn bus_name(self) -> Option<&'static str> {
+        let name = match self.bus {
+            0x01 => "pci",
+            0x03 => "usb",
+            0x05 => "bluetooth",
+            0x06 => "virtual",
+            0x10 => "isa",
+            0x11 => "i8042",
+            0x18 => "i2c",
+            0x19 => "host",
+            0x1c => "spi",
+            _ => return None,
+        };
+        Some(name)
+    }
+}
+
+/// An input device as described by a block of INPUT_DEVICE_LIST file. Only some
+/// of them are keyboards, see `is_keyboard` and `has_media_keys`.
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct Keyboard {
     pub name: String,
+    pub id: InputId,
+    // Physical path of the device, like `usb-0000:00:14.0-3/input0`
+    pub phys: String,
+    // Path of the device in sysfs without the /sys prefix
+    pub sysfs: String,
+    // Serial number, often empty
+    pub uniq: String,
+    pub handlers: Vec<String>,
+    pub prop: Capabilities,
+    pub ev: Capabilities,
+    pub key: Capabilities,
+    pub rel: Capabilities,
+    pub abs: Capabilities,
+    pub msc: Capabilities,
+    pub sw: Capabilities,
+    pub led: Capabilities,
 }
 impl Keyboard {
     // Parses a Keyboard object from a block read from INPUT_DEVICE_LIST file
@@ -11,8 +112,7 @@ impl Keyboard {
     // I: Bus=0003 Vendor=046d Product=c33a Version=0111
     // N: Name="Logitech G413 Carbon Mechanical Gaming Keyboard"
     // P: Phys=usb-0000:0b:00.3-4/input0
-    // S:
-    // Sysfs=/devices/pci0000:00/0000:00:08.1/0000:0b:00.3/usb3/3-4/3-4:1.0/0003:046D:C33A.0001/input/input2
+    // S: Sysfs=/devices/pci0000:00/0000:00:08.1/0000:0b:00.3/usb3/3-4/3-4:1.0/0003:046D:C33A.0001/input/input2
     // U: Uniq=188338553234
     // H: Handlers=sysrq kbd event2 leds
     // B: PROP=0
@@ -25,104 +125,332 @@ impl Keyboard {
     pub fn new(inp: &str) -> Keyboard {
         info!("Parsing keyboard object");
         trace!("From input:\n{}", &inp);
-        let lines = inp.split('\n');
-        let mut name = String::new();
+        let mut keyboard = Keyboard::default();
 
-        for line in lines {
-            if line.starts_with(NAME_PREFIX) {
-                name = line[NAME_PREFIX.len()..line.len() - 1].to_string();
+        for line in inp.lines() {
+            let line = line.trim_end();
+            if let Some(id) = line.strip_prefix(ID_PREFIX) {
+                keyboard.id = InputId::parse(id).unwrap_or_default();
+            } else if let Some(n) = line.strip_prefix(NAME_PREFIX) {
+                keyboard.name = n.trim_end_matches('"').to_string();
+            } else if let Some(p) = line.strip_prefix(PHYS_PREFIX) {
+                keyboard.phys = p.to_string();
+            } else if let Some(s) = line.strip_prefix(SYSFS_PREFIX) {
+                keyboard.sysfs = s.to_string();
+            } else if let Some(u) = line.strip_prefix(UNIQ_PREFIX) {
+                keyboard.uniq = u.to_string();
+            } else if let Some(h) = line.strip_prefix(HANDLERS_PREFIX) {
+                keyboard.handlers = h.split_whitespace().map(String::from).collect();
+            } else if let Some((kind, bitmap)) = line
+                .strip_prefix(BITMAP_PREFIX)
+                .and_then(|b| b.split_once('='))
+            {
+                let bitmap = Capabilities::parse(bitmap).unwrap_or_default();
+                match kind {
+                    "PROP" => keyboard.prop = bitmap,
+                    "EV" => keyboard.ev = bitmap,
+                    "KEY" => keyboard.key = bitmap,
+                    "REL" => keyboard.rel = bitmap,
+                    "ABS" => keyboard.abs = bitmap,
+                    "MSC" => keyboard.msc = bitmap,
+                    "SW" => keyboard.sw = bitmap,
+                    "LED" => keyboard.led = bitmap,
+                    _ => {}
+                }
             }
         }
-        info!("Found keyboard {}", name);
-        Keyboard { name }
+        info!(
+            "Found device {} ({:04x}:{:04x})",
+            keyboard.name, keyboard.id.vendor, keyboard.id.product
+        );
+        keyboard
+    }
+    // Builds the same description from the event file of the device with ioctls,
+    // which works for devices that aren't listed in INPUT_DEVICE_LIST file of this
+    // namespace, like in containers. Only the event handler is known.
+    pub fn from_device(path: &Path) -> io::Result<Keyboard> {
+        let file = File::open(path)?;
+        let mut id = [0u16; 4];
+        if unsafe { libc::ioctl(file.as_raw_fd(), EVIOCGID as _, id.as_mut_ptr()) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let handler = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        // /sys/class/input/event2/device links to the input2 directory of the device
+        let sysfs = fs::canonicalize(Path::new(SYS_CLASS_INPUT).join(&handler).join("device"))
+            .map(|p| p.to_string_lossy().trim_start_matches("/sys").to_string())
+            .unwrap_or_default();
+        let bits = |ev: u16| read_bits(&file, EVIOCGBIT + u32::from(ev)).unwrap_or_default();
+        Ok(Keyboard {
+            name: read_string(&file, EVIOCGNAME)?,
+            id: InputId {
+                bus: id[0],
+                vendor: id[1],
+                product: id[2],
+                version: id[3],
+            },
+            // devices without them fail with ENOENT
+            phys: read_string(&file, EVIOCGPHYS).unwrap_or_default(),
+            sysfs,
+            uniq: read_string(&file, EVIOCGUNIQ).unwrap_or_default(),
+            handlers: vec![handler],
+            prop: read_bits(&file, EVIOCGPROP).unwrap_or_default(),
+            ev: bits(0),
+            key: bits(KEY_EV),
+            rel: bits(REL_EV),
+            abs: bits(ABS_EV),
+            msc: bits(MSC_EV),
+            sw: bits(SW_EV),
+            led: bits(LED_EV),
+        })
+    }
+    // Whether the device reports keys like a regular keyboard does, that is
+    // all of the keys from KEY_ESC up to KEY_S (same check as udev's input_id)
+    pub fn is_keyboard(&self) -> bool {
+        self.ev.has(KEY_EV) && (1..32).all(|code| self.key.has(code))
+    }
+    // Whether the device reports any of the media keys, those often come from
+    // separate devices like `Consumer Control` interfaces or the `Video Bus`
+    pub fn has_media_keys(&self) -> bool {
+        self.ev.has(KEY_EV)
+            && (0..=KEY_MAX)
+                .filter(|code| self.key.has(*code))
+                .any(|code| Key::from_code(code).is_media_control())
     }
     // Attempts to open all event handler files
     pub fn handlers(&self) -> io::Result<Vec<io::Result<File>>> {
         info!("Getting event file handles");
-        let mut handlers = Vec::new();
-        let dev_inp_byid = PathBuf::from(DEV_INP_BY_ID);
-
-        let kb = format!("usb-{}", self.name.replace(" ", "_"));
-        trace!("{}", kb);
-        for file in fs::read_dir(&dev_inp_byid)? {
-            if let Ok(f) = file {
-                let p = f.path();
-                if let Some(file_name) = p.as_path().file_name() {
-                    if let Some(file_name) = file_name.to_str() {
-                        if file_name.starts_with(&kb) {
-                            trace!("found {}", file_name);
-                            handlers.push(File::open(&p.as_path()));
-                        }
-                    }
-                }
-            }
-        }
-
-        Ok(handlers)
+        Ok(self.handler_paths()?.iter().map(File::open).collect())
     }
+    // Paths of event handler files of this device, `/dev/input/eventN`
+    pub fn handler_paths(&self) -> io::Result<Vec<PathBuf>> {
+        Ok(self
+            .handlers
+            .iter()
+            .filter(|h| h.starts_with(EVENT_HANDLER_PREFIX))
+            .map(|h| Path::new(DEV_INPUT).join(h))
+            .collect())
+    }
+}
+
+// Parses all device blocks of INPUT_DEVICE_LIST file
+pub fn parse_input_devices(device_list: &str) -> Vec<Keyboard> {
+    device_list
+        .split("\n\n")
+        .filter(|dev| !dev.trim().is_empty())
+        .map(Keyboard::new)
+        .collect()
 }
 
 pub fn read_input_devices() -> io::Result<Vec<Keyboard>> {
     info!("Reading device list from {}", INPUT_DEVICE_LIST);
     let device_list = fs::read_to_string(INPUT_DEVICE_LIST)?;
 
-    // All devices with EV=120013
-    Ok(device_list
-        .split("\n\n")
-        .filter(|dev| dev.contains(KEYBOARD_INPUT_ID))
-        .map(|k| Keyboard::new(&k))
+    let mut devices = parse_input_devices(&device_list);
+    for path in unlisted_event_files(Path::new(DEV_INPUT), &devices) {
+        match Keyboard::from_device(&path) {
+            Ok(device) => devices.push(device),
+            Err(e) => trace!("failed to query {} - {}", path.display(), e),
+        }
+    }
+
+    Ok(devices
+        .into_iter()
+        .filter(|k| k.is_keyboard() || k.has_media_keys())
         .collect())
 }
 
-#[derive(Debug)]
-#[repr(C)]
+// Event files in the directory that no device of INPUT_DEVICE_LIST file lists as
+// its handler, those are described with `Keyboard::from_device` instead
+pub fn unlisted_event_files(dev_input: &Path, listed: &[Keyboard]) -> Vec<PathBuf> {
+    let entries = match fs::read_dir(dev_input) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| {
+            name.starts_with(EVENT_HANDLER_PREFIX)
+                && !listed.iter().any(|k| k.handlers.contains(name))
+        })
+        .map(|name| dev_input.join(name))
+        .collect();
+    paths.sort();
+    paths
+}
+
+// Names of links in the directory pointing to the event file, like the
+// `usb-Logitech_G413-event-kbd` links of /dev/input/by-id
+pub fn device_links(dir: &Path, event_file: &Path) -> Vec<String> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut links: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_symlink()))
+        .filter(|entry| fs::canonicalize(entry.path()).is_ok_and(|target| target == event_file))
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect();
+    links.sort();
+    links
+}
+
+// Takes (or releases) exclusive access to the device, while grabbed its events
+// are delivered only to this file descriptor
+pub fn grab_device(file: &File, grab: bool) -> io::Result<()> {
+    let arg = grab as libc::c_int;
+    if unsafe { libc::ioctl(file.as_raw_fd(), EVIOCGRAB as _, arg) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// Codes of keys that are currently held down on the device
+pub fn pressed_keys(file: &File) -> io::Result<Vec<u16>> {
+    let mut bits = [0u8; KEY_BITMAP_SIZE];
+    if unsafe { libc::ioctl(file.as_raw_fd(), EVIOCGKEY as _, bits.as_mut_ptr()) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(set_bits(&bits))
+}
+
+// Reads a string with an ioctl like EVIOCGNAME taking the length of the buffer
+fn read_string(file: &File, request: u32) -> io::Result<String> {
+    let mut buf = [0u8; 256];
+    let request = request | (buf.len() as u32) << 16;
+    let len = unsafe { libc::ioctl(file.as_raw_fd(), request as _, buf.as_mut_ptr()) };
+    if len < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let s = &buf[..len as usize];
+    let s = s.split(|b| *b == 0).next().unwrap_or(s);
+    Ok(String::from_utf8_lossy(s).into_owned())
+}
+
+// Reads a bitmap with an ioctl like EVIOCGBIT taking the length of the buffer
+fn read_bits(file: &File, request: u32) -> io::Result<Capabilities> {
+    let mut bits = [0u8; KEY_BITMAP_SIZE];
+    let request = request | (bits.len() as u32) << 16;
+    if unsafe { libc::ioctl(file.as_raw_fd(), request as _, bits.as_mut_ptr()) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Capabilities::from_bits(&set_bits(&bits)))
+}
+
+fn set_bits(bits: &[u8]) -> Vec<u16> {
+    (0..bits.len() * 8)
+        .filter(|i| bits[i / 8] & (1 << (i % 8)) != 0)
+        .map(|i| i as u16)
+        .collect()
+}
+
+/// Layout of `struct input_event`. Both fields of its timestamp are as wide as
+/// the kernel's `long`, also in 32-bit userspace built with 64-bit `time_t`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EventLayout {
+    /// 64-bit targets, events take 24 bytes
+    Long64,
+    /// 32-bit targets, events take 16 bytes
+    Long32,
+}
+impl EventLayout {
+    pub const NATIVE: EventLayout = if SIZE_OF_TIME_FIELD == 8 {
+        EventLayout::Long64
+    } else {
+        EventLayout::Long32
+    };
+    pub const fn size(self) -> usize {
+        2 * self.time_field_size() + 8
+    }
+    const fn time_field_size(self) -> usize {
+        match self {
+            EventLayout::Long64 => 8,
+            EventLayout::Long32 => 4,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct InputEvent {
-    _tv_sec: isize,  // from timeval struct
-    _tv_usec: isize, // from timeval struct
+    time: Duration,
     pub type_: u16,
     pub code: u16,
     pub value: i32,
 }
 impl InputEvent {
     pub fn new(buf: &[u8]) -> io::Result<InputEvent> {
-        let mut rdr = Cursor::new(&buf);
+        Self::with_layout(buf, EventLayout::NATIVE)
+    }
+    pub fn with_layout(buf: &[u8], layout: EventLayout) -> io::Result<InputEvent> {
+        Self::decode::<NativeEndian>(buf, layout)
+    }
+    fn decode<B: ByteOrder>(buf: &[u8], layout: EventLayout) -> io::Result<InputEvent> {
+        let mut rdr = Cursor::new(buf);
+        let sec = rdr.read_uint::<B>(layout.time_field_size())?;
+        let usec = rdr.read_uint::<B>(layout.time_field_size())?;
+        let time = Duration::from_secs(sec)
+            .checked_add(Duration::from_micros(usec))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid timestamp {}.{:06}", sec, usec),
+                )
+            })?;
         Ok(InputEvent {
-            _tv_sec: rdr
-                .read_int::<LittleEndian>(SIZE_OF_ISIZE)?
-                .try_into()
-                .unwrap(),
-            _tv_usec: rdr
-                .read_int::<LittleEndian>(SIZE_OF_ISIZE)?
-                .try_into()
-                .unwrap(),
-            type_: rdr.read_u16::<LittleEndian>()?,
-            code: rdr.read_u16::<LittleEndian>()?,
-            value: rdr.read_i32::<LittleEndian>()?,
+            time,
+            type_: rdr.read_u16::<B>()?,
+            code: rdr.read_u16::<B>()?,
+            value: rdr.read_i32::<B>()?,
         })
     }
-    pub fn is_shift(&self) -> bool {
-        match self.as_enum() {
-            Key::LShift | Key::RShift => true,
-            _ => false,
+    // Event that wasn't read from a device, its timestamp is zero
+    pub fn synthetic(type_: u16, code: u16, value: i32) -> InputEvent {
+        InputEvent {
+            time: Duration::default(),
+            type_,
+            code,
+            value,
         }
     }
-    pub fn is_ctrl(&self) -> bool {
-        match self.as_enum() {
-            Key::LCtrl | Key::RCtrl => true,
-            _ => false,
+    pub fn with_time(mut self, time: Duration) -> Self {
+        self.time = time;
+        self
+    }
+    // Encodes the event in the native layout
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(SIZE_OF_INPUT_EVENT);
+        let usec = u64::from(self.time.subsec_micros());
+        for field in &[self.time.as_secs(), usec] {
+            match EventLayout::NATIVE {
+                EventLayout::Long64 => buf.extend_from_slice(&field.to_ne_bytes()),
+                EventLayout::Long32 => buf.extend_from_slice(&(*field as u32).to_ne_bytes()),
+            }
         }
+        buf.extend_from_slice(&self.type_.to_ne_bytes());
+        buf.extend_from_slice(&self.code.to_ne_bytes());
+        buf.extend_from_slice(&self.value.to_ne_bytes());
+        buf
+    }
+    // Time at which the kernel received the event, measured by CLOCK_REALTIME
+    // unless the clock of the device was changed
+    pub fn time(&self) -> Duration {
+        self.time
+    }
+    pub fn is_shift(&self) -> bool {
+        matches!(self.as_enum(), Key::LShift | Key::RShift)
+    }
+    pub fn is_ctrl(&self) -> bool {
+        matches!(self.as_enum(), Key::LCtrl | Key::RCtrl)
     }
     pub fn is_alt(&self) -> bool {
-        match self.as_enum() {
-            Key::LAlt | Key::RAlt => true,
-            _ => false,
-        }
+        matches!(self.as_enum(), Key::LAlt | Key::RAlt)
     }
     pub fn is_super(&self) -> bool {
-        match self.as_enum() {
-            Key::Super => true,
-            _ => false,
-        }
+        matches!(self.as_enum(), Key::LSuper | Key::RSuper)
     }
     pub fn is_key_event(&self) -> bool {
         self.type_ == KEY_EV
@@ -133,6 +461,9 @@ impl InputEvent {
     pub fn is_key_release(&self) -> bool {
         self.value == KEY_RELEASE
     }
+    pub fn is_key_repeat(&self) -> bool {
+        self.value == KEY_REPEAT
+    }
     pub fn as_enum(&self) -> Key {
         Key::from_code(self.code)
     }
@@ -141,13 +472,13 @@ impl InputEvent {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use byteorder::LittleEndian;
     #[test]
     fn parses_keyboard() {
         let kb_txt = "I: Bus=0003 Vendor=046d Product=c33a Version=0111
 N: Name=\"Logitech G413 Carbon Mechanical Gaming Keyboard\"
 P: Phys=usb-0000:0b:00.3-4/input0
-S:
-Sysfs=/devices/pci0000:00/0000:00:08.1/0000:0b:00.3/usb3/3-4/3-4:1.0/0003:046D:C33A.0001/input/input2
+S: Sysfs=/devices/pci0000:00/0000:00:08.1/0000:0b:00.3/usb3/3-4/3-4:1.0/0003:046D:C33A.0001/input/input2
 U: Uniq=188338553234
 H: Handlers=sysrq kbd event2 leds
 B: PROP=0
@@ -157,8 +488,306 @@ B: MSC=10
 B: LED=7";
         let kb = Keyboard {
             name: "Logitech G413 Carbon Mechanical Gaming Keyboard".to_string(),
+            id: InputId {
+                bus: 0x0003,
+                vendor: 0x046d,
+                product: 0xc33a,
+                version: 0x0111,
+            },
+            phys: "usb-0000:0b:00.3-4/input0".to_string(),
+            sysfs: "/devices/pci0000:00/0000:00:08.1/0000:0b:00.3/usb3/3-4/3-4:1.0/0003:046D:C33A.0001/input/input2".to_string(),
+            uniq: "188338553234".to_string(),
+            handlers: vec![
+                "sysrq".to_string(),
+                "kbd".to_string(),
+                "event2".to_string(),
+                "leds".to_string(),
+            ],
+            prop: Capabilities::parse("0").unwrap(),
+            ev: Capabilities::parse("120013").unwrap(),
+            key: Capabilities::parse(
+                "1000000000007 ff9f207ac14057ff febeffdfffefffff fffffffffffffffe",
+            )
+            .unwrap(),
+            msc: Capabilities::parse("10").unwrap(),
+            led: Capabilities::parse("7").unwrap(),
+            ..Default::default()
         };
-        let parsed_kb = Keyboard::new(&kb_txt);
+        let parsed_kb = Keyboard::new(kb_txt);
         assert_eq!(kb, parsed_kb);
+        assert_eq!(parsed_kb.id.bus_name(), Some("usb"));
+        assert!(parsed_kb.prop.is_empty() && parsed_kb.rel.is_empty());
+    }
+
+    // Names and event files of devices that rkd would listen to
+    fn usable_devices(device_list: &str) -> Vec<(String, Vec<PathBuf>)> {
+        parse_input_devices(device_list)
+            .into_iter()
+            .filter(|k| k.is_keyboard() || k.has_media_keys())
+            .map(|k| (k.name.clone(), k.handler_paths().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_capabilities() {
+        let key =
+            Capabilities::parse("402000000 3803078f800d001 feffffdfffefffff fffffffffffffffe")
+                .unwrap();
+        assert!(!key.has(0));
+        assert!(key.has(1));
+        assert!(key.has(63));
+        assert!(key.has(64));
+        assert!(key.has(113));
+        assert!(!key.has(1000));
+        assert!(Capabilities::parse("12zz").is_none());
+        assert!(Capabilities::parse("0 0").unwrap().is_empty());
+    }
+
+    #[test]
+    fn detects_keyboards_on_laptop() {
+        let devices = usable_devices(include_str!("../fixtures/devices_laptop"));
+        assert_eq!(
+            devices,
+            vec![
+                (
+                    "AT Translated Set 2 keyboard".to_string(),
+                    vec![PathBuf::from("/dev/input/event3")]
+                ),
+                // reports the brightness keys
+                (
+                    "Video Bus".to_string(),
+                    vec![PathBuf::from("/dev/input/event6")]
+                ),
+                (
+                    "ThinkPad Extra Buttons".to_string(),
+                    vec![PathBuf::from("/dev/input/event7")]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_device_ids() {
+        let id = InputId::parse("Bus=0011 Vendor=0001 Product=0001 Version=ab54").unwrap();
+        assert_eq!(
+            id,
+            InputId {
+                bus: 0x11,
+                vendor: 1,
+                product: 1,
+                version: 0xab54
+            }
+        );
+        assert_eq!(id.bus_name(), Some("i8042"));
+        assert_eq!(InputId::parse("Bus=0042").unwrap().bus_name(), None);
+        assert!(InputId::parse("Bus=zz").is_none());
+    }
+
+    #[test]
+    fn builds_capabilities_from_bits() {
+        let bits = [1, 63, 64, 113];
+        let key = Capabilities::from_bits(&bits);
+        assert!((0..200).all(|bit| key.has(bit) == bits.contains(&bit)));
+        assert!(Capabilities::from_bits(&[]).is_empty());
+    }
+
+    #[test]
+    fn detects_keyboards_on_desktop() {
+        let all = parse_input_devices(include_str!("../fixtures/devices_desktop"));
+        assert_eq!(all.len(), 5);
+        let consumer_control = &all[1];
+        assert!(!consumer_control.is_keyboard());
+        assert!(consumer_control.has_media_keys());
+
+        let devices = usable_devices(include_str!("../fixtures/devices_desktop"));
+        let names: Vec<_> = devices.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "Logitech G413 Carbon Mechanical Gaming Keyboard",
+                "Logitech G413 Carbon Mechanical Gaming Keyboard Consumer Control",
+                "Logitech G502 HERO Gaming Mouse Keyboard",
+                "Keychron K2",
+            ]
+        );
+        assert_eq!(devices[3].1, vec![PathBuf::from("/dev/input/event9")]);
+    }
+
+    #[test]
+    fn detects_keyboards_in_vm() {
+        let all = parse_input_devices(include_str!("../fixtures/devices_vm"));
+        assert_eq!(all.len(), 5);
+        // the power button and the speaker have the kbd handler but aren't keyboards
+        assert!(all[0].key.has(116) && !all[0].is_keyboard());
+        assert!(all[4].key.is_empty() && !all[4].has_media_keys());
+        let tablet = &all[2];
+        assert_eq!(tablet.uniq, "28754-0000:00:01.2-1");
+        assert!(tablet.rel.has(8) && tablet.abs.has(0) && tablet.abs.has(1));
+        assert!(!tablet.is_keyboard());
+        assert!(all[3].prop.has(0));
+
+        let devices = usable_devices(include_str!("../fixtures/devices_vm"));
+        assert_eq!(
+            devices,
+            vec![(
+                "AT Translated Set 2 keyboard".to_string(),
+                vec![PathBuf::from("/dev/input/event1")]
+            )]
+        );
+    }
+
+    #[test]
+    fn detects_keyboards_with_macro_pad() {
+        let all = parse_input_devices(include_str!("../fixtures/devices_macropad"));
+        let names: Vec<_> = all
+            .iter()
+            .filter(|k| k.is_keyboard() || k.has_media_keys())
+            .map(|k| k.name.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "Keebio BDN9 Rev. 2",
+                "Keebio BDN9 Rev. 2 Consumer Control",
+                // security keys type one time passwords like a keyboard
+                "Yubico YubiKey OTP+FIDO+CCID",
+                "MX Keys Mini",
+            ]
+        );
+        let pad = &all[0];
+        assert_eq!((pad.id.vendor, pad.id.product), (0xcb10, 0x2133));
+        assert_eq!(pad.phys, "usb-0000:00:14.0-3/input0");
+        assert!(pad.led.has(0) && pad.led.has(4));
+        // system and consumer control are reported by the same USB interface
+        assert_eq!(all[1].phys, all[2].phys);
+        let bluetooth = &all[4];
+        assert_eq!(bluetooth.id.bus_name(), Some("bluetooth"));
+        assert_eq!(bluetooth.uniq, "d4:12:7a:dd:ee:ff");
+        assert!(bluetooth.sysfs.starts_with("/devices/virtual/misc/uhid/"));
+    }
+
+    #[test]
+    fn finds_device_links() {
+        let dir = std::env::temp_dir().join(format!("rkd-links-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let event_file = dir.join("event3");
+        File::create(&event_file).unwrap();
+        let event_file = fs::canonicalize(&event_file).unwrap();
+        std::os::unix::fs::symlink(&event_file, dir.join("usb-Pad-event-kbd")).unwrap();
+        std::os::unix::fs::symlink(dir.join("missing"), dir.join("usb-Gone-event-kbd")).unwrap();
+        assert_eq!(
+            device_links(&dir, &event_file),
+            vec!["usb-Pad-event-kbd".to_string()]
+        );
+        assert!(device_links(&dir.join("missing"), &event_file).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn finds_unlisted_event_files() {
+        let dir = std::env::temp_dir().join(format!("rkd-unlisted-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["event1", "event4", "event12", "mouse0"] {
+            File::create(dir.join(name)).unwrap();
+        }
+        let listed = parse_input_devices(include_str!("../fixtures/devices_vm"));
+        let unlisted = unlisted_event_files(&dir, &listed);
+        assert_eq!(unlisted, vec![dir.join("event12")]);
+        // a regular file doesn't answer the ioctls of an event file
+        let e = Keyboard::from_device(&unlisted[0]).unwrap_err();
+        assert_eq!(e.raw_os_error(), Some(libc::ENOTTY));
+        assert!(Keyboard::from_device(&dir.join("event13")).is_err());
+        assert!(unlisted_event_files(&dir.join("missing"), &listed).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reads_set_bits() {
+        let mut bits = [0u8; KEY_BITMAP_SIZE];
+        bits[3] = 0b0000_0001;
+        bits[15] = 0b0010_0000;
+        assert_eq!(set_bits(&bits), vec![24, 125]);
+        assert!(set_bits(&[0; 4]).is_empty());
+    }
+
+    // Pressing, holding and releasing `a` as read from the event file, with
+    // MSC_SCAN and SYN_REPORT events
+    fn decodes_recorded_events(raw: &[u8], layout: EventLayout) {
+        assert_eq!(raw.len(), 8 * layout.size());
+        let events: Vec<InputEvent> = raw
+            .chunks(layout.size())
+            .map(|buf| InputEvent::decode::<LittleEndian>(buf, layout).unwrap())
+            .collect();
+        let decoded: Vec<_> = events.iter().map(|e| (e.type_, e.code, e.value)).collect();
+        assert_eq!(
+            decoded,
+            vec![
+                (4, 4, 30),
+                (1, 30, 1),
+                (0, 0, 0),
+                (1, 30, 2),
+                (0, 0, 1),
+                (4, 4, 30),
+                (1, 30, 0),
+                (0, 0, 0),
+            ]
+        );
+        assert_eq!(events[1].time(), Duration::new(1_584_303_815, 512_093_000));
+        assert_eq!(events[6].time(), Duration::new(1_584_303_816, 98_214_000));
+        assert_eq!(
+            events[6].time() - events[1].time(),
+            Duration::from_micros(586_121)
+        );
+        assert!(events[1].is_key_press() && events[1].as_enum() == Key::A);
+        assert!(events[6].is_key_release());
+    }
+
+    #[test]
+    fn decodes_64bit_events() {
+        decodes_recorded_events(
+            include_bytes!("../fixtures/events_64bit"),
+            EventLayout::Long64,
+        );
+    }
+
+    #[test]
+    fn decodes_32bit_events() {
+        decodes_recorded_events(
+            include_bytes!("../fixtures/events_32bit"),
+            EventLayout::Long32,
+        );
+    }
+
+    #[test]
+    fn tells_modifier_sides() {
+        let event = |code| InputEvent::synthetic(KEY_EV, code, KEY_PRESS);
+        for code in &[42, 54] {
+            assert!(event(*code).is_shift());
+        }
+        for code in &[29, 97] {
+            assert!(event(*code).is_ctrl());
+        }
+        for code in &[56, 100] {
+            assert!(event(*code).is_alt());
+        }
+        assert!(event(125).is_super() && event(126).is_super() && !event(125).is_shift());
+        assert_eq!(event(97).as_enum(), Key::RCtrl);
+        assert_eq!(event(42).as_enum().generic(), Key::Shift);
+    }
+
+    #[test]
+    fn rejects_invalid_events() {
+        let short = [0u8; 20];
+        assert!(InputEvent::with_layout(&short, EventLayout::Long64).is_err());
+        assert!(InputEvent::with_layout(&short, EventLayout::Long32).is_ok());
+
+        let mut overflowing = [0xffu8; 24];
+        overflowing[16..].copy_from_slice(&[0; 8]);
+        assert!(InputEvent::with_layout(&overflowing, EventLayout::Long64).is_err());
+        assert_eq!(SIZE_OF_INPUT_EVENT, EventLayout::NATIVE.size());
+
+        let event = InputEvent::synthetic(KEY_EV, 30, KEY_PRESS);
+        assert_eq!(event.to_bytes().len(), SIZE_OF_INPUT_EVENT);
+        assert_eq!(InputEvent::new(&event.to_bytes()).unwrap(), event);
     }
 }
@@ -1,4 +1,5 @@
 use super::*;
+use std::fmt;
 
 #[derive(Clone,Copy,Debug,PartialEq,Eq,Hash)]
 #[rustfmt::skip]
@@ -34,300 +35,874 @@ pub enum Key {
     RAlt, LAlt, Alt,
     RCtrl, LCtrl, Ctrl,
     RShift, LShift, Shift,
-    Super,
+    RSuper, LSuper, Super,
     Esc,
     Backspace,
     Return,
     Space,
     Tab,
     UK,
+    // Keys without a name, bound by their key code - `code:148`
+    Code(u16),
+    // Keys bound by the scancode reported along with their key code - `scan:0x70068`
+    Scan(u32),
 
     XF86AudioRaiseVolume, XF86AudioLowerVolume,
     XF86AudioMute,
     XF86AudioPrev, XF86AudioNext,
     XF86AudioPlay, XF86AudioStop,
-
-    // Find out the exact key codes of these
     XF86MonBrightnessUp,
     XF86MonBrightnessDown,
+
+    // Remaining keys of linux/input-event-codes.h named after their KEY_* constants
+    Kpasterisk, Capslock, Numlock, Scrolllock, Kp7, Kp8, Kp9, Kpminus, Kp4, Kp5, Kp6,
+    Kpplus, Kp1, Kp2, Kp3, Kp0, Kpdot, Zenkakuhankaku, Key102nd, Ro, Katakana, Hiragana,
+    Henkan, Katakanahiragana, Muhenkan, Kpjpcomma, Kpenter, Kpslash, Sysrq, Linefeed,
+    Home, Pageup, End, Pagedown, Insert, Delete, Macro, Power, Kpequal, Kpplusminus,
+    Pause, Scale, Kpcomma, Hangeul, Hanja, Yen, Compose, Stop, Again, Props, Undo, Front,
+    Copy, Open, Paste, Find, Cut, Help, Menu, Calc, Setup, Sleep, Wakeup, File, Sendfile,
+    Deletefile, Xfer, Prog1, Prog2, Www, Msdos, Coffee, RotateDisplay, Cyclewindows, Mail,
+    Bookmarks, Computer, Back, Forward, Closecd, Ejectcd, Ejectclosecd, Record, Rewind,
+    Phone, Iso, Config, Homepage, Refresh, Exit, Move, Edit, Scrollup, Scrolldown,
+    Kpleftparen, Kprightparen, New, Redo, F13, F14, F15, F16, F17, F18, F19, F20, F21,
+    F22, F23, F24, Playcd, Pausecd, Prog3, Prog4, AllApplications, Suspend, Close, Play,
+    Fastforward, Bassboost, Print, Hp, Camera, Sound, Question, Email, Chat, Search,
+    Connect, Finance, Sport, Shop, Alterase, Cancel, Media, Switchvideomode,
+    Kbdillumtoggle, Kbdillumdown, Kbdillumup, Send, Reply, Forwardmail, Save, Documents,
+    Battery, Bluetooth, Wlan, Uwb, Unknown, VideoNext, VideoPrev, BrightnessCycle,
+    BrightnessAuto, DisplayOff, Wwan, Rfkill, Micmute, Ok, Select, Goto, Clear, Power2,
+    Option, Info, Time, Vendor, Archive, Program, Channel, Favorites, Epg, Pvr, Mhp,
+    Language, Title, Subtitle, Angle, FullScreen, Mode, Keyboard, AspectRatio, Pc, Tv,
+    Tv2, Vcr, Vcr2, Sat, Sat2, Cd, Tape, Radio, Tuner, Player, Text, Dvd, Aux, Mp3, Audio,
+    Video, Directory, List, Memo, Calendar, Red, Green, Yellow, Blue, Channelup,
+    Channeldown, First, Last, Ab, Next, Restart, Slow, Shuffle, Break, Previous, Digits,
+    Teen, Twen, Videophone, Games, Zoomin, Zoomout, Zoomreset, Wordprocessor, Editor,
+    Spreadsheet, Graphicseditor, Presentation, Database, News, Voicemail, Addressbook,
+    Messenger, Displaytoggle, Spellcheck, Logoff, Dollar, Euro, Frameback, Frameforward,
+    ContextMenu, MediaRepeat, Key10channelsup, Key10channelsdown, Images,
+    NotificationCenter, PickupPhone, HangupPhone, LinkPhone, DelEol, DelEos, InsLine,
+    DelLine, Fn, FnEsc, FnF1, FnF2, FnF3, FnF4, FnF5, FnF6, FnF7, FnF8, FnF9, FnF10,
+    FnF11, FnF12, Fn1, Fn2, FnD, FnE, FnF, FnS, FnB, FnRightShift, BrlDot1, BrlDot2,
+    BrlDot3, BrlDot4, BrlDot5, BrlDot6, BrlDot7, BrlDot8, BrlDot9, BrlDot10, Numeric0,
+    Numeric1, Numeric2, Numeric3, Numeric4, Numeric5, Numeric6, Numeric7, Numeric8,
+    Numeric9, NumericStar, NumericPound, NumericA, NumericB, NumericC, NumericD,
+    CameraFocus, WpsButton, TouchpadToggle, TouchpadOn, TouchpadOff, CameraZoomin,
+    CameraZoomout, CameraUp, CameraDown, CameraLeft, CameraRight, AttendantOn,
+    AttendantOff, AttendantToggle, LightsToggle, AlsToggle, RotateLockToggle,
+    RefreshRateToggle, Buttonconfig, Taskmanager, Journal, Controlpanel, Appselect,
+    Screensaver, Voicecommand, Assistant, KbdLayoutNext, EmojiPicker, Dictate,
+    BrightnessMin, BrightnessMax, KbdinputassistPrev, KbdinputassistNext,
+    KbdinputassistPrevgroup, KbdinputassistNextgroup, KbdinputassistAccept,
+    KbdinputassistCancel, RightUp, RightDown, LeftUp, LeftDown, RootMenu, MediaTopMenu,
+    Numeric11, Numeric12, AudioDesc, Key3dMode, NextFavorite, StopRecord, PauseRecord,
+    Vod, Unmute, Fastreverse, Slowreverse, Data, OnscreenKeyboard, PrivacyScreenToggle,
+    SelectiveScreenshot, NextElement, PreviousElement, AutopilotEngageToggle,
+    MarkWaypoint, Sos, NavChart, FishingChart, SingleRangeRadar, DualRangeRadar,
+    RadarOverlay, TraditionalSonar, ClearvuSonar, SidevuSonar, NavInfo, BrightnessMenu,
+    Macro1, Macro2, Macro3, Macro4, Macro5, Macro6, Macro7, Macro8, Macro9, Macro10,
+    Macro11, Macro12, Macro13, Macro14, Macro15, Macro16, Macro17, Macro18, Macro19,
+    Macro20, Macro21, Macro22, Macro23, Macro24, Macro25, Macro26, Macro27, Macro28,
+    Macro29, Macro30, MacroRecordStart, MacroRecordStop, MacroPresetCycle, MacroPreset1,
+    MacroPreset2, MacroPreset3, KbdLcdMenu1, KbdLcdMenu2, KbdLcdMenu3, KbdLcdMenu4,
+    KbdLcdMenu5,
+}
+// Highest key code defined by the kernel
+pub const KEY_MAX: u16 = 0x2ff;
+
+// Prefixes of tokens naming a key by its number
+const CODE_PREFIX: &str = "code:";
+const SCAN_PREFIX: &str = "scan:";
+
+// Modifiers standing for keys on either side of the keyboard, no key code maps to them
+const GENERIC_MODIFIERS: &[(&str, Key)] = &[
+    ("alt", Key::Alt),
+    ("ctrl", Key::Ctrl),
+    ("shift", Key::Shift),
+    ("super", Key::Super),
+];
+
+// Characters typed with shift along with the keys that type them
+const SHIFTED_CHARS: &[(char, Key)] = &[
+    ('~', Key::Tick),
+    (')', Key::Num0),
+    ('!', Key::Num1),
+    ('@', Key::Num2),
+    ('#', Key::Num3),
+    ('$', Key::Num4),
+    ('%', Key::Num5),
+    ('^', Key::Num6),
+    ('&', Key::Num7),
+    ('*', Key::Num8),
+    ('(', Key::Num9),
+    ('_', Key::Dash),
+    ('+', Key::Equal),
+    ('>', Key::Dot),
+    ('<', Key::Comma),
+    ('?', Key::Slash),
+    (':', Key::SemiColon),
+    ('"', Key::Apostrophe),
+    ('|', Key::BackSlash),
+    ('{', Key::LSquareBracket),
+    ('}', Key::RSquareBracket),
+];
+
+// Codes of all keys of linux/input-event-codes.h with the names used in config
+// files, sorted by code. Keys rkd knew before keep their names, the rest are
+// named after their KEY_* constants without underscores - `kpenter`, `pageup`.
+// Aliases like KEY_SCREENLOCK are left out.
+#[rustfmt::skip]
+pub const KEY_CODES: &[(u16, Key, &str)] = &[
+    (1, Key::Esc, "esc"),
+    (2, Key::Num1, "1"),
+    (3, Key::Num2, "2"),
+    (4, Key::Num3, "3"),
+    (5, Key::Num4, "4"),
+    (6, Key::Num5, "5"),
+    (7, Key::Num6, "6"),
+    (8, Key::Num7, "7"),
+    (9, Key::Num8, "8"),
+    (10, Key::Num9, "9"),
+    (11, Key::Num0, "0"),
+    (12, Key::Dash, "-"),
+    (13, Key::Equal, "="),
+    (14, Key::Backspace, "backspace"),
+    (15, Key::Tab, "tab"),
+    (16, Key::Q, "q"),
+    (17, Key::W, "w"),
+    (18, Key::E, "e"),
+    (19, Key::R, "r"),
+    (20, Key::T, "t"),
+    (21, Key::Y, "y"),
+    (22, Key::U, "u"),
+    (23, Key::I, "i"),
+    (24, Key::O, "o"),
+    (25, Key::P, "p"),
+    (26, Key::LSquareBracket, "["),
+    (27, Key::RSquareBracket, "]"),
+    (28, Key::Return, "return"),
+    (29, Key::LCtrl, "lctrl"),
+    (30, Key::A, "a"),
+    (31, Key::S, "s"),
+    (32, Key::D, "d"),
+    (33, Key::F, "f"),
+    (34, Key::G, "g"),
+    (35, Key::H, "h"),
+    (36, Key::J, "j"),
+    (37, Key::K, "k"),
+    (38, Key::L, "l"),
+    (39, Key::SemiColon, ";"),
+    (40, Key::Apostrophe, "'"),
+    (41, Key::Tick, "`"),
+    (42, Key::LShift, "lshift"),
+    (43, Key::BackSlash, "\\"),
+    (44, Key::Z, "z"),
+    (45, Key::X, "x"),
+    (46, Key::C, "c"),
+    (47, Key::V, "v"),
+    (48, Key::B, "b"),
+    (49, Key::N, "n"),
+    (50, Key::M, "m"),
+    (51, Key::Comma, ","),
+    (52, Key::Dot, "."),
+    (53, Key::Slash, "/"),
+    (54, Key::RShift, "rshift"),
+    (55, Key::Kpasterisk, "kpasterisk"),
+    (56, Key::LAlt, "lalt"),
+    (57, Key::Space, "space"),
+    (58, Key::Capslock, "capslock"),
+    (59, Key::F1, "f1"),
+    (60, Key::F2, "f2"),
+    (61, Key::F3, "f3"),
+    (62, Key::F4, "f4"),
+    (63, Key::F5, "f5"),
+    (64, Key::F6, "f6"),
+    (65, Key::F7, "f7"),
+    (66, Key::F8, "f8"),
+    (67, Key::F9, "f9"),
+    (68, Key::F10, "f10"),
+    (69, Key::Numlock, "numlock"),
+    (70, Key::Scrolllock, "scrolllock"),
+    (71, Key::Kp7, "kp7"),
+    (72, Key::Kp8, "kp8"),
+    (73, Key::Kp9, "kp9"),
+    (74, Key::Kpminus, "kpminus"),
+    (75, Key::Kp4, "kp4"),
+    (76, Key::Kp5, "kp5"),
+    (77, Key::Kp6, "kp6"),
+    (78, Key::Kpplus, "kpplus"),
+    (79, Key::Kp1, "kp1"),
+    (80, Key::Kp2, "kp2"),
+    (81, Key::Kp3, "kp3"),
+    (82, Key::Kp0, "kp0"),
+    (83, Key::Kpdot, "kpdot"),
+    (85, Key::Zenkakuhankaku, "zenkakuhankaku"),
+    (86, Key::Key102nd, "102nd"),
+    (87, Key::F11, "f11"),
+    (88, Key::F12, "f12"),
+    (89, Key::Ro, "ro"),
+    (90, Key::Katakana, "katakana"),
+    (91, Key::Hiragana, "hiragana"),
+    (92, Key::Henkan, "henkan"),
+    (93, Key::Katakanahiragana, "katakanahiragana"),
+    (94, Key::Muhenkan, "muhenkan"),
+    (95, Key::Kpjpcomma, "kpjpcomma"),
+    (96, Key::Kpenter, "kpenter"),
+    (97, Key::RCtrl, "rctrl"),
+    (98, Key::Kpslash, "kpslash"),
+    (99, Key::Sysrq, "sysrq"),
+    (100, Key::RAlt, "ralt"),
+    (101, Key::Linefeed, "linefeed"),
+    (102, Key::Home, "home"),
+    (103, Key::Up, "up"),
+    (104, Key::Pageup, "pageup"),
+    (105, Key::Left, "left"),
+    (106, Key::Right, "right"),
+    (107, Key::End, "end"),
+    (108, Key::Down, "down"),
+    (109, Key::Pagedown, "pagedown"),
+    (110, Key::Insert, "insert"),
+    (111, Key::Delete, "delete"),
+    (112, Key::Macro, "macro"),
+    (113, Key::XF86AudioMute, "xf86audiomute"),
+    (114, Key::XF86AudioLowerVolume, "xf86audiolowervolume"),
+    (115, Key::XF86AudioRaiseVolume, "xf86audioraisevolume"),
+    (116, Key::Power, "power"),
+    (117, Key::Kpequal, "kpequal"),
+    (118, Key::Kpplusminus, "kpplusminus"),
+    (119, Key::Pause, "pause"),
+    (120, Key::Scale, "scale"),
+    (121, Key::Kpcomma, "kpcomma"),
+    (122, Key::Hangeul, "hangeul"),
+    (123, Key::Hanja, "hanja"),
+    (124, Key::Yen, "yen"),
+    (125, Key::LSuper, "lsuper"),
+    (126, Key::RSuper, "rsuper"),
+    (127, Key::Compose, "compose"),
+    (128, Key::Stop, "stop"),
+    (129, Key::Again, "again"),
+    (130, Key::Props, "props"),
+    (131, Key::Undo, "undo"),
+    (132, Key::Front, "front"),
+    (133, Key::Copy, "copy"),
+    (134, Key::Open, "open"),
+    (135, Key::Paste, "paste"),
+    (136, Key::Find, "find"),
+    (137, Key::Cut, "cut"),
+    (138, Key::Help, "help"),
+    (139, Key::Menu, "menu"),
+    (140, Key::Calc, "calc"),
+    (141, Key::Setup, "setup"),
+    (142, Key::Sleep, "sleep"),
+    (143, Key::Wakeup, "wakeup"),
+    (144, Key::File, "file"),
+    (145, Key::Sendfile, "sendfile"),
+    (146, Key::Deletefile, "deletefile"),
+    (147, Key::Xfer, "xfer"),
+    (148, Key::Prog1, "prog1"),
+    (149, Key::Prog2, "prog2"),
+    (150, Key::Www, "www"),
+    (151, Key::Msdos, "msdos"),
+    (152, Key::Coffee, "coffee"),
+    (153, Key::RotateDisplay, "rotatedisplay"),
+    (154, Key::Cyclewindows, "cyclewindows"),
+    (155, Key::Mail, "mail"),
+    (156, Key::Bookmarks, "bookmarks"),
+    (157, Key::Computer, "computer"),
+    (158, Key::Back, "back"),
+    (159, Key::Forward, "forward"),
+    (160, Key::Closecd, "closecd"),
+    (161, Key::Ejectcd, "ejectcd"),
+    (162, Key::Ejectclosecd, "ejectclosecd"),
+    (163, Key::XF86AudioNext, "xf86audionext"),
+    (164, Key::XF86AudioPlay, "xf86audioplay"),
+    (165, Key::XF86AudioPrev, "xf86audioprev"),
+    (166, Key::XF86AudioStop, "xf86audiostop"),
+    (167, Key::Record, "record"),
+    (168, Key::Rewind, "rewind"),
+    (169, Key::Phone, "phone"),
+    (170, Key::Iso, "iso"),
+    (171, Key::Config, "config"),
+    (172, Key::Homepage, "homepage"),
+    (173, Key::Refresh, "refresh"),
+    (174, Key::Exit, "exit"),
+    (175, Key::Move, "move"),
+    (176, Key::Edit, "edit"),
+    (177, Key::Scrollup, "scrollup"),
+    (178, Key::Scrolldown, "scrolldown"),
+    (179, Key::Kpleftparen, "kpleftparen"),
+    (180, Key::Kprightparen, "kprightparen"),
+    (181, Key::New, "new"),
+    (182, Key::Redo, "redo"),
+    (183, Key::F13, "f13"),
+    (184, Key::F14, "f14"),
+    (185, Key::F15, "f15"),
+    (186, Key::F16, "f16"),
+    (187, Key::F17, "f17"),
+    (188, Key::F18, "f18"),
+    (189, Key::F19, "f19"),
+    (190, Key::F20, "f20"),
+    (191, Key::F21, "f21"),
+    (192, Key::F22, "f22"),
+    (193, Key::F23, "f23"),
+    (194, Key::F24, "f24"),
+    (200, Key::Playcd, "playcd"),
+    (201, Key::Pausecd, "pausecd"),
+    (202, Key::Prog3, "prog3"),
+    (203, Key::Prog4, "prog4"),
+    (204, Key::AllApplications, "allapplications"),
+    (205, Key::Suspend, "suspend"),
+    (206, Key::Close, "close"),
+    (207, Key::Play, "play"),
+    (208, Key::Fastforward, "fastforward"),
+    (209, Key::Bassboost, "bassboost"),
+    (210, Key::Print, "print"),
+    (211, Key::Hp, "hp"),
+    (212, Key::Camera, "camera"),
+    (213, Key::Sound, "sound"),
+    (214, Key::Question, "question"),
+    (215, Key::Email, "email"),
+    (216, Key::Chat, "chat"),
+    (217, Key::Search, "search"),
+    (218, Key::Connect, "connect"),
+    (219, Key::Finance, "finance"),
+    (220, Key::Sport, "sport"),
+    (221, Key::Shop, "shop"),
+    (222, Key::Alterase, "alterase"),
+    (223, Key::Cancel, "cancel"),
+    (224, Key::XF86MonBrightnessDown, "xf86monbrightnessdown"),
+    (225, Key::XF86MonBrightnessUp, "xf86monbrightnessup"),
+    (226, Key::Media, "media"),
+    (227, Key::Switchvideomode, "switchvideomode"),
+    (228, Key::Kbdillumtoggle, "kbdillumtoggle"),
+    (229, Key::Kbdillumdown, "kbdillumdown"),
+    (230, Key::Kbdillumup, "kbdillumup"),
+    (231, Key::Send, "send"),
+    (232, Key::Reply, "reply"),
+    (233, Key::Forwardmail, "forwardmail"),
+    (234, Key::Save, "save"),
+    (235, Key::Documents, "documents"),
+    (236, Key::Battery, "battery"),
+    (237, Key::Bluetooth, "bluetooth"),
+    (238, Key::Wlan, "wlan"),
+    (239, Key::Uwb, "uwb"),
+    (240, Key::Unknown, "unknown"),
+    (241, Key::VideoNext, "videonext"),
+    (242, Key::VideoPrev, "videoprev"),
+    (243, Key::BrightnessCycle, "brightnesscycle"),
+    (244, Key::BrightnessAuto, "brightnessauto"),
+    (245, Key::DisplayOff, "displayoff"),
+    (246, Key::Wwan, "wwan"),
+    (247, Key::Rfkill, "rfkill"),
+    (248, Key::Micmute, "micmute"),
+    (352, Key::Ok, "ok"),
+    (353, Key::Select, "select"),
+    (354, Key::Goto, "goto"),
+    (355, Key::Clear, "clear"),
+    (356, Key::Power2, "power2"),
+    (357, Key::Option, "option"),
+    (358, Key::Info, "info"),
+    (359, Key::Time, "time"),
+    (360, Key::Vendor, "vendor"),
+    (361, Key::Archive, "archive"),
+    (362, Key::Program, "program"),
+    (363, Key::Channel, "channel"),
+    (364, Key::Favorites, "favorites"),
+    (365, Key::Epg, "epg"),
+    (366, Key::Pvr, "pvr"),
+    (367, Key::Mhp, "mhp"),
+    (368, Key::Language, "language"),
+    (369, Key::Title, "title"),
+    (370, Key::Subtitle, "subtitle"),
+    (371, Key::Angle, "angle"),
+    (372, Key::FullScreen, "fullscreen"),
+    (373, Key::Mode, "mode"),
+    (374, Key::Keyboard, "keyboard"),
+    (375, Key::AspectRatio, "aspectratio"),
+    (376, Key::Pc, "pc"),
+    (377, Key::Tv, "tv"),
+    (378, Key::Tv2, "tv2"),
+    (379, Key::Vcr, "vcr"),
+    (380, Key::Vcr2, "vcr2"),
+    (381, Key::Sat, "sat"),
+    (382, Key::Sat2, "sat2"),
+    (383, Key::Cd, "cd"),
+    (384, Key::Tape, "tape"),
+    (385, Key::Radio, "radio"),
+    (386, Key::Tuner, "tuner"),
+    (387, Key::Player, "player"),
+    (388, Key::Text, "text"),
+    (389, Key::Dvd, "dvd"),
+    (390, Key::Aux, "aux"),
+    (391, Key::Mp3, "mp3"),
+    (392, Key::Audio, "audio"),
+    (393, Key::Video, "video"),
+    (394, Key::Directory, "directory"),
+    (395, Key::List, "list"),
+    (396, Key::Memo, "memo"),
+    (397, Key::Calendar, "calendar"),
+    (398, Key::Red, "red"),
+    (399, Key::Green, "green"),
+    (400, Key::Yellow, "yellow"),
+    (401, Key::Blue, "blue"),
+    (402, Key::Channelup, "channelup"),
+    (403, Key::Channeldown, "channeldown"),
+    (404, Key::First, "first"),
+    (405, Key::Last, "last"),
+    (406, Key::Ab, "ab"),
+    (407, Key::Next, "next"),
+    (408, Key::Restart, "restart"),
+    (409, Key::Slow, "slow"),
+    (410, Key::Shuffle, "shuffle"),
+    (411, Key::Break, "break"),
+    (412, Key::Previous, "previous"),
+    (413, Key::Digits, "digits"),
+    (414, Key::Teen, "teen"),
+    (415, Key::Twen, "twen"),
+    (416, Key::Videophone, "videophone"),
+    (417, Key::Games, "games"),
+    (418, Key::Zoomin, "zoomin"),
+    (419, Key::Zoomout, "zoomout"),
+    (420, Key::Zoomreset, "zoomreset"),
+    (421, Key::Wordprocessor, "wordprocessor"),
+    (422, Key::Editor, "editor"),
+    (423, Key::Spreadsheet, "spreadsheet"),
+    (424, Key::Graphicseditor, "graphicseditor"),
+    (425, Key::Presentation, "presentation"),
+    (426, Key::Database, "database"),
+    (427, Key::News, "news"),
+    (428, Key::Voicemail, "voicemail"),
+    (429, Key::Addressbook, "addressbook"),
+    (430, Key::Messenger, "messenger"),
+    (431, Key::Displaytoggle, "displaytoggle"),
+    (432, Key::Spellcheck, "spellcheck"),
+    (433, Key::Logoff, "logoff"),
+    (434, Key::Dollar, "dollar"),
+    (435, Key::Euro, "euro"),
+    (436, Key::Frameback, "frameback"),
+    (437, Key::Frameforward, "frameforward"),
+    (438, Key::ContextMenu, "contextmenu"),
+    (439, Key::MediaRepeat, "mediarepeat"),
+    (440, Key::Key10channelsup, "10channelsup"),
+    (441, Key::Key10channelsdown, "10channelsdown"),
+    (442, Key::Images, "images"),
+    (444, Key::NotificationCenter, "notificationcenter"),
+    (445, Key::PickupPhone, "pickupphone"),
+    (446, Key::HangupPhone, "hangupphone"),
+    (447, Key::LinkPhone, "linkphone"),
+    (448, Key::DelEol, "deleol"),
+    (449, Key::DelEos, "deleos"),
+    (450, Key::InsLine, "insline"),
+    (451, Key::DelLine, "delline"),
+    (464, Key::Fn, "fn"),
+    (465, Key::FnEsc, "fnesc"),
+    (466, Key::FnF1, "fnf1"),
+    (467, Key::FnF2, "fnf2"),
+    (468, Key::FnF3, "fnf3"),
+    (469, Key::FnF4, "fnf4"),
+    (470, Key::FnF5, "fnf5"),
+    (471, Key::FnF6, "fnf6"),
+    (472, Key::FnF7, "fnf7"),
+    (473, Key::FnF8, "fnf8"),
+    (474, Key::FnF9, "fnf9"),
+    (475, Key::FnF10, "fnf10"),
+    (476, Key::FnF11, "fnf11"),
+    (477, Key::FnF12, "fnf12"),
+    (478, Key::Fn1, "fn1"),
+    (479, Key::Fn2, "fn2"),
+    (480, Key::FnD, "fnd"),
+    (481, Key::FnE, "fne"),
+    (482, Key::FnF, "fnf"),
+    (483, Key::FnS, "fns"),
+    (484, Key::FnB, "fnb"),
+    (485, Key::FnRightShift, "fnrightshift"),
+    (497, Key::BrlDot1, "brldot1"),
+    (498, Key::BrlDot2, "brldot2"),
+    (499, Key::BrlDot3, "brldot3"),
+    (500, Key::BrlDot4, "brldot4"),
+    (501, Key::BrlDot5, "brldot5"),
+    (502, Key::BrlDot6, "brldot6"),
+    (503, Key::BrlDot7, "brldot7"),
+    (504, Key::BrlDot8, "brldot8"),
+    (505, Key::BrlDot9, "brldot9"),
+    (506, Key::BrlDot10, "brldot10"),
+    (512, Key::Numeric0, "numeric0"),
+    (513, Key::Numeric1, "numeric1"),
+    (514, Key::Numeric2, "numeric2"),
+    (515, Key::Numeric3, "numeric3"),
+    (516, Key::Numeric4, "numeric4"),
+    (517, Key::Numeric5, "numeric5"),
+    (518, Key::Numeric6, "numeric6"),
+    (519, Key::Numeric7, "numeric7"),
+    (520, Key::Numeric8, "numeric8"),
+    (521, Key::Numeric9, "numeric9"),
+    (522, Key::NumericStar, "numericstar"),
+    (523, Key::NumericPound, "numericpound"),
+    (524, Key::NumericA, "numerica"),
+    (525, Key::NumericB, "numericb"),
+    (526, Key::NumericC, "numericc"),
+    (527, Key::NumericD, "numericd"),
+    (528, Key::CameraFocus, "camerafocus"),
+    (529, Key::WpsButton, "wpsbutton"),
+    (530, Key::TouchpadToggle, "touchpadtoggle"),
+    (531, Key::TouchpadOn, "touchpadon"),
+    (532, Key::TouchpadOff, "touchpadoff"),
+    (533, Key::CameraZoomin, "camerazoomin"),
+    (534, Key::CameraZoomout, "camerazoomout"),
+    (535, Key::CameraUp, "cameraup"),
+    (536, Key::CameraDown, "cameradown"),
+    (537, Key::CameraLeft, "cameraleft"),
+    (538, Key::CameraRight, "cameraright"),
+    (539, Key::AttendantOn, "attendanton"),
+    (540, Key::AttendantOff, "attendantoff"),
+    (541, Key::AttendantToggle, "attendanttoggle"),
+    (542, Key::LightsToggle, "lightstoggle"),
+    (560, Key::AlsToggle, "alstoggle"),
+    (561, Key::RotateLockToggle, "rotatelocktoggle"),
+    (562, Key::RefreshRateToggle, "refreshratetoggle"),
+    (576, Key::Buttonconfig, "buttonconfig"),
+    (577, Key::Taskmanager, "taskmanager"),
+    (578, Key::Journal, "journal"),
+    (579, Key::Controlpanel, "controlpanel"),
+    (580, Key::Appselect, "appselect"),
+    (581, Key::Screensaver, "screensaver"),
+    (582, Key::Voicecommand, "voicecommand"),
+    (583, Key::Assistant, "assistant"),
+    (584, Key::KbdLayoutNext, "kbdlayoutnext"),
+    (585, Key::EmojiPicker, "emojipicker"),
+    (586, Key::Dictate, "dictate"),
+    (592, Key::BrightnessMin, "brightnessmin"),
+    (593, Key::BrightnessMax, "brightnessmax"),
+    (608, Key::KbdinputassistPrev, "kbdinputassistprev"),
+    (609, Key::KbdinputassistNext, "kbdinputassistnext"),
+    (610, Key::KbdinputassistPrevgroup, "kbdinputassistprevgroup"),
+    (611, Key::KbdinputassistNextgroup, "kbdinputassistnextgroup"),
+    (612, Key::KbdinputassistAccept, "kbdinputassistaccept"),
+    (613, Key::KbdinputassistCancel, "kbdinputassistcancel"),
+    (614, Key::RightUp, "rightup"),
+    (615, Key::RightDown, "rightdown"),
+    (616, Key::LeftUp, "leftup"),
+    (617, Key::LeftDown, "leftdown"),
+    (618, Key::RootMenu, "rootmenu"),
+    (619, Key::MediaTopMenu, "mediatopmenu"),
+    (620, Key::Numeric11, "numeric11"),
+    (621, Key::Numeric12, "numeric12"),
+    (622, Key::AudioDesc, "audiodesc"),
+    (623, Key::Key3dMode, "3dmode"),
+    (624, Key::NextFavorite, "nextfavorite"),
+    (625, Key::StopRecord, "stoprecord"),
+    (626, Key::PauseRecord, "pauserecord"),
+    (627, Key::Vod, "vod"),
+    (628, Key::Unmute, "unmute"),
+    (629, Key::Fastreverse, "fastreverse"),
+    (630, Key::Slowreverse, "slowreverse"),
+    (631, Key::Data, "data"),
+    (632, Key::OnscreenKeyboard, "onscreenkeyboard"),
+    (633, Key::PrivacyScreenToggle, "privacyscreentoggle"),
+    (634, Key::SelectiveScreenshot, "selectivescreenshot"),
+    (635, Key::NextElement, "nextelement"),
+    (636, Key::PreviousElement, "previouselement"),
+    (637, Key::AutopilotEngageToggle, "autopilotengagetoggle"),
+    (638, Key::MarkWaypoint, "markwaypoint"),
+    (639, Key::Sos, "sos"),
+    (640, Key::NavChart, "navchart"),
+    (641, Key::FishingChart, "fishingchart"),
+    (642, Key::SingleRangeRadar, "singlerangeradar"),
+    (643, Key::DualRangeRadar, "dualrangeradar"),
+    (644, Key::RadarOverlay, "radaroverlay"),
+    (645, Key::TraditionalSonar, "traditionalsonar"),
+    (646, Key::ClearvuSonar, "clearvusonar"),
+    (647, Key::SidevuSonar, "sidevusonar"),
+    (648, Key::NavInfo, "navinfo"),
+    (649, Key::BrightnessMenu, "brightnessmenu"),
+    (656, Key::Macro1, "macro1"),
+    (657, Key::Macro2, "macro2"),
+    (658, Key::Macro3, "macro3"),
+    (659, Key::Macro4, "macro4"),
+    (660, Key::Macro5, "macro5"),
+    (661, Key::Macro6, "macro6"),
+    (662, Key::Macro7, "macro7"),
+    (663, Key::Macro8, "macro8"),
+    (664, Key::Macro9, "macro9"),
+    (665, Key::Macro10, "macro10"),
+    (666, Key::Macro11, "macro11"),
+    (667, Key::Macro12, "macro12"),
+    (668, Key::Macro13, "macro13"),
+    (669, Key::Macro14, "macro14"),
+    (670, Key::Macro15, "macro15"),
+    (671, Key::Macro16, "macro16"),
+    (672, Key::Macro17, "macro17"),
+    (673, Key::Macro18, "macro18"),
+    (674, Key::Macro19, "macro19"),
+    (675, Key::Macro20, "macro20"),
+    (676, Key::Macro21, "macro21"),
+    (677, Key::Macro22, "macro22"),
+    (678, Key::Macro23, "macro23"),
+    (679, Key::Macro24, "macro24"),
+    (680, Key::Macro25, "macro25"),
+    (681, Key::Macro26, "macro26"),
+    (682, Key::Macro27, "macro27"),
+    (683, Key::Macro28, "macro28"),
+    (684, Key::Macro29, "macro29"),
+    (685, Key::Macro30, "macro30"),
+    (688, Key::MacroRecordStart, "macrorecordstart"),
+    (689, Key::MacroRecordStop, "macrorecordstop"),
+    (690, Key::MacroPresetCycle, "macropresetcycle"),
+    (691, Key::MacroPreset1, "macropreset1"),
+    (692, Key::MacroPreset2, "macropreset2"),
+    (693, Key::MacroPreset3, "macropreset3"),
+    (696, Key::KbdLcdMenu1, "kbdlcdmenu1"),
+    (697, Key::KbdLcdMenu2, "kbdlcdmenu2"),
+    (698, Key::KbdLcdMenu3, "kbdlcdmenu3"),
+    (699, Key::KbdLcdMenu4, "kbdlcdmenu4"),
+    (700, Key::KbdLcdMenu5, "kbdlcdmenu5"),
+];
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name())
+    }
 }
+
 impl Key {
+    // Codes without a name in the table map to `Key::Code`, KEY_RESERVED and
+    // codes above KEY_MAX to `Key::UK`
     pub fn from_code(code: u16) -> Self {
-        use self::Key::*;
-        match code {
-            1 => Esc,
-            2 => Num1,
-            3 => Num2,
-            4 => Num3,
-            5 => Num4,
-            6 => Num5,
-            7 => Num6,
-            8 => Num7,
-            9 => Num8,
-            10 => Num9,
-            11 => Num0,
-            12 => Dash,
-            13 => Equal,
-            14 => Backspace,
-            15 => Tab,
-            16 => Q,
-            17 => W,
-            18 => E,
-            19 => R,
-            20 => T,
-            21 => Y,
-            22 => U,
-            23 => I,
-            24 => O,
-            25 => P,
-            26 => LSquareBracket,
-            27 => RSquareBracket,
-            28 => Return,
-            29 => Ctrl, // LCtrl
-            30 => A,
-            31 => S,
-            32 => D,
-            33 => F,
-            34 => G,
-            35 => H,
-            36 => J,
-            37 => K,
-            38 => L,
-            39 => SemiColon,
-            40 => Apostrophe,
-            41 => Tick,
-            42 => Shift, // LShift
-            43 => BackSlash,
-            44 => Z,
-            45 => X,
-            46 => C,
-            47 => V,
-            48 => B,
-            49 => N,
-            50 => M,
-            51 => Comma,
-            52 => Dot,
-            53 => Slash,
-            54 => Shift, // RShift
-            56 => Alt,   // LAlt
-            57 => Space,
-            59 => F1,
-            60 => F2,
-            61 => F3,
-            62 => F4,
-            63 => F5,
-            64 => F6,
-            65 => F7,
-            66 => F8,
-            67 => F9,
-            68 => F10,
-            87 => F11,
-            88 => F12,
-            97 => Ctrl, // RCtrl
-            100 => Alt, // RAlt
-            103 => Up,
-            105 => Left,
-            106 => Right,
-            108 => Down,
-            113 => XF86AudioMute,
-            114 => XF86AudioLowerVolume,
-            115 => XF86AudioRaiseVolume,
-            163 => XF86AudioNext,
-            164 => XF86AudioPlay,
-            165 => XF86AudioPrev,
-            166 => XF86AudioStop,
-            125 => Super,
-            _ => UK,
+        match KEY_CODES.binary_search_by_key(&code, |(code, _, _)| *code) {
+            Ok(i) => KEY_CODES[i].1,
+            Err(_) if code == 0 || code > KEY_MAX => Key::UK,
+            Err(_) => Key::Code(code),
+        }
+    }
+    // Code of the key, generic modifiers and scancodes don't have one
+    pub fn code(self) -> Option<u16> {
+        if let Key::Code(code) = self {
+            return Some(code);
         }
+        KEY_CODES
+            .iter()
+            .find(|(_, key, _)| *key == self)
+            .map(|(code, _, _)| *code)
     }
+    // Finds the key by its case insensitive name
+    fn from_name(name: &str) -> Option<Key> {
+        let name = name.to_lowercase();
+        KEY_CODES
+            .iter()
+            .map(|(_, key, key_name)| (*key_name, *key))
+            .chain(GENERIC_MODIFIERS.iter().copied())
+            .find(|(key_name, _)| *key_name == name)
+            .map(|(_, key)| key)
+    }
+    // Parses `code:NNN` and `scan:0xNN` tokens, a code with a name gives the named key
+    fn from_number(token: &str) -> Option<Key> {
+        let prefix = token.get(..CODE_PREFIX.len())?.to_lowercase();
+        let number = &token[CODE_PREFIX.len()..];
+        let number = match number.strip_prefix("0x").or(number.strip_prefix("0X")) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        match prefix.as_str() {
+            CODE_PREFIX if number <= u32::from(KEY_MAX) => match Key::from_code(number as u16) {
+                Key::UK => None,
+                key => Some(key),
+            },
+            CODE_PREFIX => None,
+            SCAN_PREFIX => Some(Key::Scan(number)),
+            _ => None,
+        }
+    }
+    // Parses a token of a keybinding. Uppercase letters and characters typed with
+    // shift parse to the key along with `shift` - `A` is `shift + a`.
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(token: &str) -> Vec<Self> {
         trace!("parsing token {}", token);
-        use self::Key::*;
-        let mut parsed_keys = Vec::new();
-        let lowercased_token = token.to_lowercase();
-        // all keys that dont have their coresponding shift version
-        let is_unique = match lowercased_token.as_ref() {
-            "alt"
-            | "ctrl"
-            | "super"
-            | "shift"
-            | "esc"
-            | "backspace"
-            | "return"
-            | "space"
-            | "tab"
-            | "f1"
-            | "f2"
-            | "f3"
-            | "f4"
-            | "f5"
-            | "f6"
-            | "f7"
-            | "f8"
-            | "f9"
-            | "f10"
-            | "f11"
-            | "f12"
-            | "up"
-            | "down"
-            | "left"
-            | "right"
-            | "xf86audiomute"
-            | "xf86audio
-lowervolume"
-            | "xf86audioraisevolume"
-            | "xf86audionext"
-            | "xf86audioplay"
-            | "xf86audioprev"
-            | "xf86audiostop" => true,
-            _ => false,
-        };
-        if is_unique {
-            match lowercased_token.as_ref() {
-                "alt" => parsed_keys.push(Alt),
-                "ctrl" => parsed_keys.push(Ctrl),
-                "shift" => parsed_keys.push(Shift),
-                "super" => parsed_keys.push(Super),
-                "esc" => parsed_keys.push(Esc),
-                "backspace" => parsed_keys.push(Backspace),
-                "return" => parsed_keys.push(Return),
-                "space" => parsed_keys.push(Space),
-                "tab" => parsed_keys.push(Tab),
-                "f1" => parsed_keys.push(F1),
-                "f2" => parsed_keys.push(F2),
-                "f3" => parsed_keys.push(F3),
-                "f4" => parsed_keys.push(F4),
-                "f5" => parsed_keys.push(F5),
-                "f6" => parsed_keys.push(F6),
-                "f7" => parsed_keys.push(F7),
-                "f8" => parsed_keys.push(F8),
-                "f9" => parsed_keys.push(F9),
-                "f10" => parsed_keys.push(F10),
-                "f11" => parsed_keys.push(F11),
-                "f12" => parsed_keys.push(F12),
-                "up" => parsed_keys.push(Up),
-                "down" => parsed_keys.push(Down),
-                "left" => parsed_keys.push(Left),
-                "right" => parsed_keys.push(Right),
-                "xf86audiomute" => parsed_keys.push(XF86AudioMute),
-                "xf86audiolowervolume" => parsed_keys.push(XF86AudioLowerVolume),
-                "xf86audioraisevolume" => parsed_keys.push(XF86AudioRaiseVolume),
-                "xf86audionext" => parsed_keys.push(XF86AudioNext),
-                "xf86audioplay" => parsed_keys.push(XF86AudioPlay),
-                "xf86audioprev" => parsed_keys.push(XF86AudioPrev),
-                "xf86audiostop" => parsed_keys.push(XF86AudioStop),
-                // We exhaust all the options so this branch is unreachable
-                _ => unreachable!(),
+        if let Some(key) = Self::from_number(token) {
+            return vec![key];
+        }
+        let mut chars = token.chars();
+        if let (Some(ch), None) = (chars.next(), chars.next()) {
+            if let Some((_, key)) = SHIFTED_CHARS.iter().find(|(shifted, _)| *shifted == ch) {
+                return vec![Key::Shift, *key];
             }
-        } else {
-            // All shift modified tokens are single characters
-            if let Some(ch) = token.chars().next() {
-                let is_shift_modified = match ch {
-                    '!'
-                    | '@'
-                    | '#'
-                    | '$'
-                    | '%'
-                    | '^'
-                    | '&'
-                    | '*'
-                    | '('
-                    | ')'
-                    | '_'
-                    | '+'
-                    | '<'
-                    | '>'
-                    | '?'
-                    | ':'
-                    | '\"'
-                    | '|'
-                    | '{'
-                    | '}'
-                    | '~'
-                    | 'A'..='Z' => true,
-                    _ => false,
-                };
-                if is_shift_modified {
-                    parsed_keys.push(Shift);
-                }
-                match ch {
-                    '`' | '~' => parsed_keys.push(Tick),
-                    '0' | ')' => parsed_keys.push(Num0),
-                    '1' | '!' => parsed_keys.push(Num1),
-                    '2' | '@' => parsed_keys.push(Num2),
-                    '3' | '#' => parsed_keys.push(Num3),
-                    '4' | '$' => parsed_keys.push(Num4),
-                    '5' | '%' => parsed_keys.push(Num5),
-                    '6' | '^' => parsed_keys.push(Num6),
-                    '7' | '&' => parsed_keys.push(Num7),
-                    '8' | '*' => parsed_keys.push(Num8),
-                    '9' | '(' => parsed_keys.push(Num9),
-                    '-' | '_' => parsed_keys.push(Dash),
-                    '=' | '+' => parsed_keys.push(Equal),
-                    '.' | '>' => parsed_keys.push(Dot),
-                    ',' | '<' => parsed_keys.push(Comma),
-                    '/' | '?' => parsed_keys.push(Slash),
-                    ';' | ':' => parsed_keys.push(SemiColon),
-                    '\'' | '"' => parsed_keys.push(Apostrophe),
-                    '\\' | '|' => parsed_keys.push(BackSlash),
-                    '[' | '{' => parsed_keys.push(LSquareBracket),
-                    ']' | '}' => parsed_keys.push(RSquareBracket),
-                    'a' | 'A' => parsed_keys.push(A),
-                    'b' | 'B' => parsed_keys.push(B),
-                    'c' | 'C' => parsed_keys.push(C),
-                    'd' | 'D' => parsed_keys.push(D),
-                    'e' | 'E' => parsed_keys.push(E),
-                    'f' | 'F' => parsed_keys.push(F),
-                    'g' | 'G' => parsed_keys.push(G),
-                    'h' | 'H' => parsed_keys.push(H),
-                    'i' | 'I' => parsed_keys.push(I),
-                    'j' | 'J' => parsed_keys.push(J),
-                    'k' | 'K' => parsed_keys.push(K),
-                    'l' | 'L' => parsed_keys.push(L),
-                    'm' | 'M' => parsed_keys.push(M),
-                    'n' | 'N' => parsed_keys.push(N),
-                    'o' | 'O' => parsed_keys.push(O),
-                    'p' | 'P' => parsed_keys.push(P),
-                    'q' | 'Q' => parsed_keys.push(Q),
-                    'r' | 'R' => parsed_keys.push(R),
-                    's' | 'S' => parsed_keys.push(S),
-                    't' | 'T' => parsed_keys.push(T),
-                    'u' | 'U' => parsed_keys.push(U),
-                    'v' | 'V' => parsed_keys.push(V),
-                    'w' | 'W' => parsed_keys.push(W),
-                    'x' | 'X' => parsed_keys.push(X),
-                    'y' | 'Y' => parsed_keys.push(Y),
-                    'z' | 'Z' => parsed_keys.push(Z),
-                    _ => parsed_keys.push(UK),
-                }
+            if ch.is_ascii_uppercase() {
+                return vec![Key::Shift, Self::from_name(token).unwrap_or(Key::UK)];
             }
         }
-        parsed_keys
+        vec![Self::from_name(token).unwrap_or(Key::UK)]
     }
 
-    pub fn is_modifier(self) -> bool {
+    // Returns the name of a known key closest to the token if there is one
+    // that is similar enough to be a likely typo.
+    pub fn closest_name(token: &str) -> Option<&'static str> {
+        let token = token.to_lowercase();
+        let max_distance = (token.chars().count() / 3).max(1);
+        KEY_CODES
+            .iter()
+            .map(|(_, _, name)| *name)
+            .chain(GENERIC_MODIFIERS.iter().map(|(name, _)| *name))
+            // single characters are too short to tell typos apart
+            .filter(|name| name.chars().count() > 1)
+            .map(|name| (name, edit_distance(&token, name)))
+            .filter(|(_, distance)| *distance <= max_distance)
+            .min_by_key(|(_, distance)| *distance)
+            .map(|(name, _)| name)
+    }
+
+    // Name of the key as it would be written in a config file
+    pub fn name(self) -> String {
+        match self {
+            Key::Code(code) => return format!("{}{}", CODE_PREFIX, code),
+            Key::Scan(scan) => return format!("{}{:#x}", SCAN_PREFIX, scan),
+            _ => {}
+        }
+        KEY_CODES
+            .iter()
+            .map(|(_, key, name)| (*name, *key))
+            .chain(GENERIC_MODIFIERS.iter().copied())
+            .find(|(_, key)| *key == self)
+            .map(|(name, _)| name.to_string())
+            .unwrap_or_else(|| format!("{:?}", self).to_lowercase())
+    }
+
+    // Whether any key code maps to this key or, for generic modifiers, to one of its sides
+    pub fn is_reachable(self) -> bool {
+        match self {
+            Key::Code(code) => return Key::from_code(code) == self,
+            Key::Scan(_) => return true,
+            _ => {}
+        }
+        KEY_CODES
+            .iter()
+            .any(|(_, key, _)| *key == self || key.generic() == self)
+    }
+    // The modifier standing for both sides of a left or right modifier key
+    pub fn generic(self) -> Key {
         use self::Key::*;
         match self {
-            Shift | Alt | Super | Ctrl => true,
-            _ => false,
+            LShift | RShift => Shift,
+            LCtrl | RCtrl => Ctrl,
+            LAlt | RAlt => Alt,
+            LSuper | RSuper => Super,
+            key => key,
         }
     }
+
+    pub fn is_modifier(self) -> bool {
+        use self::Key::*;
+        matches!(self.generic(), Shift | Alt | Super | Ctrl)
+    }
     pub fn is_media_control(self) -> bool {
         use self::Key::*;
-        match self {
+        matches!(
+            self,
             XF86AudioMute
-            | XF86AudioNext
-            | XF86AudioPlay
-            | XF86AudioPrev
-            | XF86AudioStop
-            | XF86AudioLowerVolume
-            | XF86AudioRaiseVolume
-            | XF86MonBrightnessUp
-            | XF86MonBrightnessDown => true,
-            _ => false,
-        }
+                | XF86AudioNext
+                | XF86AudioPlay
+                | XF86AudioPrev
+                | XF86AudioStop
+                | XF86AudioLowerVolume
+                | XF86AudioRaiseVolume
+                | XF86MonBrightnessUp
+                | XF86MonBrightnessDown
+        )
     }
     pub fn is_action(self) -> bool {
         !Self::is_modifier(self) && !Self::is_media_control(self)
     }
+    // Keys bound by their number, like vendor macro keys
+    pub fn is_numbered(self) -> bool {
+        matches!(self, Key::Code(_) | Key::Scan(_))
+    }
+}
+
+// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            current[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(current[j] + 1);
+        }
+        prev = current;
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_every_key() {
+        // every KEY_* constant of linux/input-event-codes.h except KEY_RESERVED,
+        // KEY_MAX, KEY_CNT and aliases
+        assert_eq!(KEY_CODES.len(), 503);
+        assert!(KEY_CODES.windows(2).all(|w| w[0].0 < w[1].0));
+        for (code, key, name) in KEY_CODES {
+            assert!(*code <= KEY_MAX);
+            assert_eq!(Key::from_code(*code), *key, "{}", name);
+            assert_eq!(key.code(), Some(*code), "{}", name);
+            assert_eq!(key.name(), *name);
+            assert_eq!(key.to_string(), *name);
+            assert_eq!(Key::from_str(name), vec![*key], "{}", name);
+            assert_eq!(Key::from_str(&name.to_uppercase()).last(), Some(key));
+            assert!(key.is_reachable());
+        }
+        for (name, key) in GENERIC_MODIFIERS {
+            assert_eq!(Key::from_str(name), vec![*key]);
+            assert_eq!(key.name(), *name);
+            assert_eq!(key.code(), None);
+            assert!(key.is_modifier() && key.is_reachable());
+        }
+        assert_eq!(Key::from_code(0), Key::UK);
+        assert_eq!(Key::from_code(KEY_MAX + 1), Key::UK);
+        assert_eq!(Key::from_code(KEY_MAX), Key::Code(KEY_MAX));
+    }
+
+    #[test]
+    fn parses_key_numbers() {
+        assert_eq!(Key::from_str("code:16"), vec![Key::Q]);
+        assert_eq!(Key::from_str("code:0x10"), vec![Key::Q]);
+        assert_eq!(Key::from_str("CODE:767"), vec![Key::Code(767)]);
+        assert_eq!(Key::from_str("code:0"), vec![Key::UK]);
+        assert_eq!(Key::from_str("code:768"), vec![Key::UK]);
+        assert_eq!(Key::from_str("code:"), vec![Key::UK]);
+        assert_eq!(Key::from_str("code:q"), vec![Key::UK]);
+        assert_eq!(Key::from_str("scan:0x70068"), vec![Key::Scan(0x70068)]);
+        assert_eq!(Key::from_str("scan:219"), vec![Key::Scan(0xdb)]);
+
+        let key = Key::from_code(0x2f0);
+        assert_eq!(key, Key::Code(0x2f0));
+        assert_eq!(key.code(), Some(0x2f0));
+        assert_eq!(key.name(), "code:752");
+        assert!(key.is_action() && key.is_reachable());
+        assert!(!Key::Code(0).is_reachable());
+        assert_eq!(Key::Scan(0x70068).name(), "scan:0x70068");
+        assert_eq!(Key::Scan(0x70068).code(), None);
+    }
+
+    #[test]
+    fn parses_key_names() {
+        let table = vec![
+            ("kpenter", Key::Kpenter, 96),
+            ("PageUp", Key::Pageup, 104),
+            ("sysrq", Key::Sysrq, 99),
+            ("capslock", Key::Capslock, 58),
+            ("f24", Key::F24, 194),
+            ("rsuper", Key::RSuper, 126),
+            ("XF86MonBrightnessUp", Key::XF86MonBrightnessUp, 225),
+            ("102nd", Key::Key102nd, 86),
+        ];
+        for (name, key, code) in table {
+            assert_eq!(Key::from_str(name), vec![key]);
+            assert_eq!(Key::from_code(code), key);
+        }
+        assert_eq!(Key::from_str("A"), vec![Key::Shift, Key::A]);
+        assert_eq!(Key::from_str("?"), vec![Key::Shift, Key::Slash]);
+        assert_eq!(Key::from_str("kpentr"), vec![Key::UK]);
+        assert_eq!(Key::closest_name("kpentr"), Some("kpenter"));
+        assert_eq!(Key::RSuper.generic(), Key::Super);
+    }
 }
@@ -1,14 +1,41 @@
-#![feature(or_patterns)]
+pub mod check;
+pub mod chord;
 pub mod config;
+pub mod devices;
+pub mod epoll;
+pub mod error;
+pub mod exec;
+pub mod expand;
+pub mod inotify;
 pub mod input;
 pub mod key;
+pub mod manager;
+pub mod matcher;
+pub mod reload;
+pub mod repeat;
+pub mod scope;
+pub mod session;
+pub mod uinput;
+pub mod xkb;
+use crate::chord::*;
 use crate::config::*;
+use crate::epoll::*;
+use crate::error::*;
+use crate::exec::*;
+use crate::expand::*;
+use crate::inotify::*;
 use crate::input::*;
 use crate::key::*;
-use byteorder::{LittleEndian, ReadBytesExt};
+use crate::manager::*;
+use crate::matcher::*;
+use crate::repeat::*;
+use crate::scope::*;
+use crate::session::*;
+use crate::uinput::*;
+use crate::xkb::*;
+use byteorder::{ByteOrder, NativeEndian, ReadBytesExt};
 use log::{error, info, trace};
 use std::clone::Clone;
-use std::convert::TryInto;
 use std::fs::{self, File};
 use std::io::{self, Cursor, Read};
 use std::mem;
@@ -16,111 +43,115 @@ use std::path::{Path, PathBuf};
 use std::process::{Child, Command};
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Duration;
 
-const DEV_INP_BY_ID: &'static str = "/dev/input/by-id";
-const INPUT_DEVICE_LIST: &'static str = "/proc/bus/input/devices";
-const KEYBOARD_INPUT_ID: &'static str = "120013";
-const NAME_PREFIX: &'static str = "N: Name=\"";
-// Each input event consist of exactly 24 bytes (see InputEvent struct)
-const SIZE_OF_INPUT_EVENT: usize = mem::size_of::<InputEvent>();
-const SIZE_OF_ISIZE: usize = mem::size_of::<isize>();
+const DEV_INPUT: &str = "/dev/input";
+// Directories of /dev/input with links named after the vendor, model and serial
+// number of devices and after the port they are plugged into
+const BY_ID_DIR: &str = "by-id";
+const BY_PATH_DIR: &str = "by-path";
+const SYS_CLASS_INPUT: &str = "/sys/class/input";
+const INPUT_DEVICE_LIST: &str = "/proc/bus/input/devices";
+const ID_PREFIX: &str = "I: ";
+const NAME_PREFIX: &str = "N: Name=\"";
+const PHYS_PREFIX: &str = "P: Phys=";
+const SYSFS_PREFIX: &str = "S: Sysfs=";
+const UNIQ_PREFIX: &str = "U: Uniq=";
+const HANDLERS_PREFIX: &str = "H: Handlers=";
+// Capability bitmaps - `B: KEY=1000000000007 ff9f207ac14057ff`
+const BITMAP_PREFIX: &str = "B: ";
+const EVENT_HANDLER_PREFIX: &str = "event";
+const VIRTUAL_KEYBOARD_NAME: &str = "rkd virtual keyboard";
+// Width of words of capability bitmaps
+const BITS_PER_LONG: usize = mem::size_of::<libc::c_ulong>() * 8;
+// Each field of the timestamp of input events is as wide as the kernel's long
+const SIZE_OF_TIME_FIELD: usize = mem::size_of::<libc::c_ulong>();
+// 24 bytes on 64-bit and 16 bytes on 32-bit targets (see EventLayout)
+const SIZE_OF_INPUT_EVENT: usize = EventLayout::NATIVE.size();
 
-const KEY_EV: u16 = 1;
+const KEY_EV: u16 = 1; // EV_KEY
+const SYN_EV: u16 = 0; // EV_SYN
+const SYN_REPORT: u16 = 0;
+const SYN_DROPPED: u16 = 3;
+const REL_EV: u16 = 2; // EV_REL
+const ABS_EV: u16 = 3; // EV_ABS
+const MSC_EV: u16 = 4; // EV_MSC
+const MSC_SCAN: u16 = 4;
+const SW_EV: u16 = 5; // EV_SW
+const LED_EV: u16 = 0x11; // EV_LED
+
+// Size of the key state bitmap returned by EVIOCGKEY
+const KEY_BITMAP_SIZE: usize = KEY_MAX as usize / 8 + 1;
+
+// ioctl requests, see linux/input.h and linux/uinput.h
+const EVIOCGRAB: u32 = 0x4004_4590; // _IOW('E', 0x90, int)
+const EVIOCGID: u32 = 0x8008_4502; // _IOR('E', 0x02, struct input_id)
+const EVIOCGNAME: u32 = 0x8000_4506; // _IOC(_IOC_READ, 'E', 0x06, len)
+const EVIOCGPHYS: u32 = 0x8000_4507; // _IOC(_IOC_READ, 'E', 0x07, len)
+const EVIOCGUNIQ: u32 = 0x8000_4508; // _IOC(_IOC_READ, 'E', 0x08, len)
+const EVIOCGPROP: u32 = 0x8000_4509; // _IOC(_IOC_READ, 'E', 0x09, len)
+const EVIOCGBIT: u32 = 0x8000_4520; // _IOC(_IOC_READ, 'E', 0x20 + ev, len)
+const EVIOCGABS: u32 = 0x8018_4540; // _IOR('E', 0x40 + abs, struct input_absinfo)
+const EVIOCGKEY: u32 = 0x8000_4518 | (KEY_BITMAP_SIZE as u32) << 16; // _IOC(_IOC_READ, 'E', 0x18, len)
+const UI_SET_EVBIT: u32 = 0x4004_5564; // _IOW('U', 100, int)
+const UI_SET_KEYBIT: u32 = 0x4004_5565; // _IOW('U', 101, int)
+const UI_SET_RELBIT: u32 = 0x4004_5566; // _IOW('U', 102, int)
+const UI_SET_ABSBIT: u32 = 0x4004_5567; // _IOW('U', 103, int)
+const UI_SET_MSCBIT: u32 = 0x4004_5568; // _IOW('U', 104, int)
+const UI_SET_LEDBIT: u32 = 0x4004_5569; // _IOW('U', 105, int)
+const UI_SET_SWBIT: u32 = 0x4004_556d; // _IOW('U', 109, int)
+const UI_DEV_CREATE: u32 = 0x5501; // _IO('U', 1)
+const UI_DEV_DESTROY: u32 = 0x5502; // _IO('U', 2)
 const KEY_RELEASE: i32 = 0;
 const KEY_PRESS: i32 = 1;
+const KEY_REPEAT: i32 = 2;
+
+#[derive(Clone, Debug, Default)]
+pub struct ListenOptions {
+    // Form chords from keys pressed on different keyboards
+    pub merge_key_states: bool,
+    // Take exclusive access to keyboards and pass through only unbound keys
+    pub grab: bool,
+    // Where commands get the variables of the graphical session from
+    pub session_env: SessionEnv,
+    pub chains: ChainOptions,
+    // File the name of the current mode is written to
+    pub mode_file: Option<PathBuf>,
+    // Notified after the config was reloaded
+    pub reloads: Option<Notifier>,
+}
 
-pub fn run_rkd(keybindings: Keybindings) {
+pub fn run_rkd(kb: Arc<Mutex<Config>>, options: ListenOptions) {
     info!("Starting rkd");
-    trace!("{:?}", &keybindings);
-    match read_input_devices() {
-        Ok(keyboards) => {
-            let kb = Arc::new(Mutex::new(keybindings));
-            for k in keyboards {
-                match k.handlers() {
-                    Ok(handlers) => {
-                        let mut thr_handles = Vec::new();
-                        for h in handlers {
-                            let _kb = kb.clone();
-                            let handle = thread::spawn(|| {
-                                listen(h.expect("failed to open input file"), _kb);
-                            });
-                            thr_handles.push((&k.name, handle));
-                        }
-                        for (kb, handle) in thr_handles {
-                            handle.join().map_err(|_| {
-                                error!("failed to join a thread handle for keyboard {}", kb)
-                            });
-                        }
-                    }
-                    Err(e) => {
-                        error!("Failed to read handlers for keyboard {} - {}", k.name, e);
-                        std::process::exit(1);
-                    }
-                }
-            }
+    trace!("{:?}", &kb);
+    let result = DeviceManager::new(kb).and_then(|manager| {
+        if options.grab {
+            check_uinput().map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("failed to create {} - {}", VIRTUAL_KEYBOARD_NAME, e),
+                )
+            })?;
         }
-        Err(e) => eprintln!(
-            "Error: failed while reading '{}' file - {}",
-            INPUT_DEVICE_LIST, e
-        ),
+        manager
+            .merge_key_states(options.merge_key_states)
+            .session_env(options.session_env)
+            .chain_options(options.chains)
+            .mode_file(options.mode_file)
+            .reloads(options.reloads)
+            .grab(options.grab)
+            .run()
+    });
+    if let Err(e) = result {
+        error!("Device manager failed - {}", e);
+        std::process::exit(1);
     }
 }
 
-pub fn listen(mut event_file: File, keybindings: Arc<Mutex<Keybindings>>) {
-    let mut buf: [u8; SIZE_OF_INPUT_EVENT] = [0; SIZE_OF_INPUT_EVENT];
-    let mut key_combination: Vec<Key> = Vec::new();
-    loop {
-        match event_file.read(&mut buf) {
-            Ok(num_of_bytes) => {
-                if num_of_bytes != SIZE_OF_INPUT_EVENT {
-                    error!("invalid input {:?}", &buf);
-                    continue;
-                }
-
-                match InputEvent::new(&buf) {
-                    Ok(event) => {
-                        if event.is_key_event() {
-                            if event.is_key_press() {
-                                let k = event.as_enum();
-                                trace!("Pressed {:?}, key_code: {}", k, event.code);
-                                key_combination.push(k);
-                            } else if event.is_key_release() {
-                                let k = event.as_enum();
-                                trace!("Released {:?}", k);
-                                let mut remove_idx = 0;
-                                let mut remove = false;
-                                for (i, key) in key_combination.iter().enumerate() {
-                                    if *key == k {
-                                        remove_idx = i;
-                                        remove = true;
-                                        break;
-                                    }
-                                }
-                                if remove {
-                                    key_combination.remove(remove_idx);
-                                }
-                            }
-                            trace!("Current key combination: {:?}", key_combination);
-
-                            match keybindings.lock() {
-                                Ok(mut keybindings) => {
-                                    if let Some(exec) = keybindings.get_mut(&key_combination) {
-                                        info!("running cmd {:?}", exec);
-                                        exec.run().map_err(|e| {
-                                            error!("failed to execute command - {}", e)
-                                        });
-                                    }
-                                }
-                                Err(e) => error!("faild to aquire lock for keybindings - {}", e),
-                            }
-                        }
-                    }
-                    Err(e) => error!("Error: failed parsing InputEvent - {}", e),
-                }
-            }
-            Err(e) => {
-                error!("invalid input event - {}", e);
-            }
-        }
+pub fn run_exec(exec: &Exec, env: &[(String, String)]) {
+    info!("running cmd {:?}", exec);
+    trace!("session environment {:?}", env);
+    if let Err(e) = exec.spawn_reaped(env) {
+        error!("failed to execute command - {}", e);
     }
 }
@@ -1,12 +1,186 @@
+use rkd::check::*;
 use rkd::config::*;
+use rkd::devices::*;
+use rkd::epoll::*;
+use rkd::exec::*;
+use rkd::key::*;
+use rkd::reload::*;
+use rkd::repeat::*;
+use rkd::session::*;
 use rkd::*;
 use std::env;
+use std::process;
+use std::sync::{Arc, Mutex};
+
+const USAGE: &str =
+    "Usage: rkd [check] [--direct | --shell <shell>] [--watch] [--merge-devices] [--grab]
+           [--session-env <file | systemd>] [--chain-timeout <duration>]
+           [--chain-abort <key>] [--mode-file <file>] <config>
+       rkd list-devices [--json]
+
+Commands:
+    check           validate the config without listening to input devices
+    list-devices    list input devices, whether rkd listens to them and whether
+                    they can be opened, `--json` prints them as JSON
+
+Options:
+    --direct           execute commands without a shell
+    --shell <shell>    execute commands with `<shell> -c`
+    --watch            reload the config whenever the file changes
+    --merge-devices    allow chords with keys pressed on different keyboards
+    --grab             hide keybindings from other applications, other keys are
+                       passed through a virtual copy of each keyboard (needs access
+                       to /dev/uinput)
+    --session-env <file | systemd>
+                       run commands with WAYLAND_DISPLAY, DISPLAY, XDG_RUNTIME_DIR
+                       and DBUS_SESSION_BUS_ADDRESS read from the file or from
+                       the systemd user manager
+    --chain-timeout <duration>
+                       abandon a started chain of chords if its next chord isn't
+                       pressed in time - `500ms`, `2s`
+    --chain-abort <key>
+                       key that abandons a started chain, `esc` by default
+    --mode-file <file> write the name of the current mode to the file whenever
+                       it changes";
 
 fn main() {
     env_logger::init();
-    let args = env::args();
-    let cfg_file = args.skip(1).next().expect("Provide a path to config file");
-    let c = Cfg::new(cfg_file);
-    let kb = c.parse().unwrap();
-    run_rkd(kb);
+    let mut args = env::args().skip(1).peekable();
+    if args
+        .peek()
+        .map(|arg| arg == "list-devices")
+        .unwrap_or(false)
+    {
+        args.next();
+        run_list_devices(args);
+    }
+    let is_check = args.peek().map(|arg| arg == "check").unwrap_or(false);
+    if is_check {
+        args.next();
+    }
+    let mut exec_mode = ExecMode::default();
+    let mut cfg_file = None;
+    let mut watch = false;
+    let mut options = ListenOptions::default();
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--direct" => exec_mode = ExecMode::Direct,
+            "--watch" => watch = true,
+            "--merge-devices" => options.merge_key_states = true,
+            "--grab" => options.grab = true,
+            "--session-env" => match args.next() {
+                Some(source) => options.session_env = SessionEnv::from_arg(&source),
+                None => exit_with_usage(),
+            },
+            "--chain-timeout" => match args.next().as_deref().and_then(parse_duration) {
+                Some(timeout) => options.chains.timeout = Some(timeout),
+                None => exit_with_usage(),
+            },
+            "--chain-abort" => match args.next().map(|key| Key::from_str(&key)).as_deref() {
+                Some(&[key]) if key != Key::UK => options.chains.abort_key = key,
+                _ => exit_with_usage(),
+            },
+            "--mode-file" => match args.next() {
+                Some(file) => options.mode_file = Some(file.into()),
+                None => exit_with_usage(),
+            },
+            "--shell" => match args.next() {
+                Some(shell) => exec_mode = ExecMode::Shell(shell),
+                None => exit_with_usage(),
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            flag if flag.starts_with('-') => {
+                eprintln!("error: unknown option '{}'\n", flag);
+                exit_with_usage();
+            }
+            _ if cfg_file.is_some() => {
+                eprintln!("error: unexpected argument '{}'\n", arg);
+                exit_with_usage();
+            }
+            _ => cfg_file = Some(arg),
+        }
+    }
+    let cfg_file = cfg_file.unwrap_or_else(|| exit_with_usage());
+    let c = Cfg::new(cfg_file).exec_mode(exec_mode);
+
+    if is_check {
+        run_check(&c);
+    }
+    match c.parse() {
+        Ok(kb) => {
+            let kb = Arc::new(Mutex::new(kb));
+            let reloads = Notifier::new().and_then(|reloads| {
+                spawn_reloader(c, kb.clone(), watch, reloads.clone())?;
+                Ok(reloads)
+            });
+            options.reloads = match reloads {
+                Ok(reloads) => Some(reloads),
+                Err(e) => {
+                    eprintln!("error: failed to set up config reloading - {}", e);
+                    process::exit(1);
+                }
+            };
+            run_rkd(kb, options);
+        }
+        Err(errors) => {
+            for e in &errors {
+                eprintln!("{}\n", e);
+            }
+            eprintln!("error: aborting due to {} error(s) in config", errors.len());
+            process::exit(1);
+        }
+    }
+}
+
+fn run_check(c: &Cfg<String>) -> ! {
+    let report = check(c);
+    for warning in &report.warnings {
+        eprintln!("{}\n", warning);
+    }
+    for e in &report.errors {
+        eprintln!("{}\n", e);
+    }
+    if report.is_ok() {
+        println!(
+            "{}: ok, {} warning(s)",
+            c.file().display(),
+            report.warnings.len()
+        );
+        process::exit(0);
+    } else {
+        eprintln!(
+            "{}: {} error(s), {} warning(s)",
+            c.file().display(),
+            report.errors.len(),
+            report.warnings.len()
+        );
+        process::exit(1);
+    }
+}
+
+fn run_list_devices(args: impl Iterator<Item = String>) -> ! {
+    let mut json = false;
+    for arg in args {
+        match arg.as_ref() {
+            "--json" => json = true,
+            _ => exit_with_usage(),
+        }
+    }
+    match list_devices() {
+        Ok(reports) if json => print!("{}", format_json(&reports)),
+        Ok(reports) => print!("{}", format_text(&reports)),
+        Err(e) => {
+            eprintln!("error: failed to list input devices - {}", e);
+            process::exit(1);
+        }
+    }
+    process::exit(0);
+}
+
+fn exit_with_usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(1);
 }
@@ -0,0 +1,944 @@
+//! Listens to all connected keyboards in a single event loop, opening new ones
+//! when keyboards are plugged in after rkd was started.
+use super::*;
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::time::Instant;
+
+const DEV_INPUT_EVENT_MASK: u32 =
+    libc::IN_CREATE | libc::IN_DELETE | libc::IN_ATTRIB | libc::IN_MOVED_TO;
+// udev needs a moment after the event node appears to set its permissions
+const RESCAN_DELAY: Duration = Duration::from_millis(500);
+// Device tokens are their file descriptors, so this one can't collide with any
+const INOTIFY_TOKEN: u64 = u64::MAX;
+const RELOAD_TOKEN: u64 = u64::MAX - 1;
+// Number of events read with a single syscall
+const EVENT_BATCH_SIZE: usize = 64;
+
+#[derive(Debug, PartialEq)]
+enum GrabState {
+    Off,
+    // Waiting for all keys to be released
+    Pending,
+    Grabbed,
+}
+
+// Keybinding that runs again on autorepeat of its key
+#[derive(Debug)]
+struct Repeating {
+    code: u16,
+    repeat: Repeat,
+    exec: Exec,
+    last_run: Duration,
+}
+
+#[derive(Debug)]
+struct Device {
+    keyboard: Keyboard,
+    // Names of links to the event file in /dev/input/by-id
+    links: Vec<String>,
+    // Canonical path of the event file
+    path: PathBuf,
+    file: File,
+    key_state: KeyState,
+    grab: GrabState,
+    // Codes of keys that triggered a keybinding and aren't passed through until released
+    swallowed: Vec<u16>,
+    // Codes of keys held down on this device
+    held: Vec<u16>,
+    // Events read since the last SYN_REPORT
+    frame: Vec<InputEvent>,
+    // Set after SYN_DROPPED until the next SYN_REPORT, events in between are discarded
+    dropped: bool,
+    // Set while the key that triggered a repeating keybinding is held
+    repeating: Option<Repeating>,
+    // Scancode of the MSC_SCAN event reported right before a key event
+    scan: Option<u32>,
+    matcher: Matcher,
+    // Copy of the keyboard receiving all events that aren't swallowed while it's grabbed
+    virtual_keyboard: Option<VirtualKeyboard>,
+}
+impl Device {
+    // Grabs the device once none of its keys are held, grabbing it with a key
+    // held down would leave the key pressed for other applications
+    fn try_grab(&mut self) {
+        match pressed_keys(&self.file) {
+            Ok(keys) if keys.is_empty() => match grab_device(&self.file, true) {
+                Ok(()) => {
+                    info!("Grabbed {}", self.keyboard.name);
+                    self.grab = GrabState::Grabbed;
+                }
+                Err(e) => {
+                    error!("failed to grab {} - {}", self.keyboard.name, e);
+                    self.grab = GrabState::Off;
+                }
+            },
+            Ok(_) => trace!("Waiting for keys of {} to be released", self.keyboard.name),
+            Err(e) => {
+                error!(
+                    "failed to read pressed keys of {} - {}",
+                    self.keyboard.name, e
+                );
+                self.grab = GrabState::Off;
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct DeviceManager {
+    keybindings: Arc<Mutex<Config>>,
+    // Name of the mode whose keybindings are active
+    mode: String,
+    // File the name of the current mode is written to whenever it changes
+    mode_file: Option<PathBuf>,
+    epoll: Epoll,
+    // Open devices keyed by their epoll token
+    devices: HashMap<u64, Device>,
+    // When set keys pressed on different keyboards form a single chord
+    merge_key_states: bool,
+    shared_key_state: KeyState,
+    shared_matcher: Matcher,
+    chains: ChainOptions,
+    // Grab keyboards and pass through their unbound keys
+    grab: bool,
+    launcher: Launcher,
+    // Notified by the reloader thread once the config was swapped
+    reloads: Option<Notifier>,
+}
+impl DeviceManager {
+    pub fn new(keybindings: Arc<Mutex<Config>>) -> io::Result<DeviceManager> {
+        Ok(DeviceManager {
+            keybindings,
+            mode: DEFAULT_MODE.to_string(),
+            mode_file: None,
+            epoll: Epoll::new()?,
+            devices: HashMap::new(),
+            merge_key_states: false,
+            shared_key_state: KeyState::default(),
+            shared_matcher: Matcher::default(),
+            chains: ChainOptions::default(),
+            grab: false,
+            launcher: Launcher::default(),
+            reloads: None,
+        })
+    }
+    pub fn merge_key_states(mut self, merge: bool) -> Self {
+        self.merge_key_states = merge;
+        self
+    }
+    pub fn chain_options(mut self, chains: ChainOptions) -> Self {
+        self.chains = chains;
+        self
+    }
+    pub fn session_env(mut self, session_env: SessionEnv) -> Self {
+        self.launcher = Launcher::new(session_env);
+        self
+    }
+    pub fn grab(mut self, grab: bool) -> Self {
+        self.grab = grab;
+        self
+    }
+    pub fn mode_file(mut self, mode_file: Option<PathBuf>) -> Self {
+        self.mode_file = mode_file;
+        self
+    }
+    pub fn reloads(mut self, reloads: Option<Notifier>) -> Self {
+        self.reloads = reloads;
+        self
+    }
+    pub fn mode(&self) -> &str {
+        &self.mode
+    }
+
+    // Listens to all keyboards and keeps watching /dev/input for new ones.
+    // Removed keyboards are dropped once reading their event file fails.
+    pub fn run(&mut self) -> io::Result<()> {
+        let mut inotify = Inotify::new()?;
+        inotify.add_watch(Path::new(DEV_INPUT), DEV_INPUT_EVENT_MASK)?;
+        self.epoll.add(inotify.as_raw_fd(), INOTIFY_TOKEN)?;
+        if let Some(reloads) = &self.reloads {
+            self.epoll.add(reloads.as_raw_fd(), RELOAD_TOKEN)?;
+        }
+        self.rescan();
+        self.write_mode_file();
+
+        let mut rescan_at: Option<Instant> = None;
+        loop {
+            let timeout = rescan_at.map(|at| at.saturating_duration_since(Instant::now()));
+            for token in self.epoll.wait(timeout)? {
+                if token == INOTIFY_TOKEN {
+                    let names = inotify.read_names()?;
+                    trace!("{} changed - {:?}", DEV_INPUT, names);
+                    rescan_at.get_or_insert_with(|| Instant::now() + RESCAN_DELAY);
+                } else if token == RELOAD_TOKEN {
+                    self.reloaded();
+                } else if let Some(device) = self.led_owner(token) {
+                    self.forward_leds(device);
+                } else {
+                    self.read_device(token);
+                }
+            }
+            if rescan_at.is_some_and(|at| at <= Instant::now()) {
+                rescan_at = None;
+                self.rescan();
+            }
+        }
+    }
+
+    // Opens keyboards that aren't being listened to yet
+    pub fn rescan(&mut self) {
+        let keyboards = match read_input_devices() {
+            Ok(keyboards) => keyboards,
+            Err(e) => {
+                error!("failed while reading '{}' file - {}", INPUT_DEVICE_LIST, e);
+                return;
+            }
+        };
+        for k in keyboards
+            .into_iter()
+            .filter(|k| k.name != VIRTUAL_KEYBOARD_NAME)
+        {
+            let paths = match k.handler_paths() {
+                Ok(paths) => paths,
+                Err(e) => {
+                    error!("Failed to read handlers for keyboard {} - {}", k.name, e);
+                    continue;
+                }
+            };
+            for path in paths {
+                let event_file = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+                if self.devices.values().any(|d| d.path == event_file) {
+                    continue;
+                }
+                // LED changes are written back to grabbed keyboards
+                let file = match open_device(&path, self.grab) {
+                    Err(e) if self.grab => {
+                        trace!("failed to open {} for writing - {}", path.display(), e);
+                        open_device(&path, false)
+                    }
+                    file => file,
+                };
+                match file {
+                    Ok(file) => {
+                        info!("Listening to {} ({})", k.name, event_file.display());
+                        if let Err(e) = self.add_device(&k, event_file, file) {
+                            error!("failed to listen to {} - {}", path.display(), e);
+                        }
+                    }
+                    Err(e) => error!("failed to open {} - {}", path.display(), e),
+                }
+            }
+        }
+    }
+
+    fn add_device(&mut self, keyboard: &Keyboard, path: PathBuf, file: File) -> io::Result<u64> {
+        let token = file.as_raw_fd() as u64;
+        let virtual_keyboard = if self.grab {
+            VirtualKeyboard::new(keyboard, &file)
+                .map_err(|e| {
+                    error!(
+                        "failed to create {} for {}, not grabbing it - {}",
+                        VIRTUAL_KEYBOARD_NAME, keyboard.name, e
+                    )
+                })
+                .ok()
+        } else {
+            None
+        };
+        self.epoll.add(file.as_raw_fd(), token)?;
+        if let Some(virtual_keyboard) = &virtual_keyboard {
+            let fd = virtual_keyboard.as_raw_fd();
+            if let Err(e) = self.epoll.add(fd, fd as u64) {
+                let _ = self.epoll.delete(file.as_raw_fd());
+                return Err(e);
+            }
+        }
+        let mut device = Device {
+            keyboard: keyboard.clone(),
+            links: device_links(&Path::new(DEV_INPUT).join(BY_ID_DIR), &path),
+            path,
+            file,
+            key_state: KeyState::default(),
+            grab: GrabState::Off,
+            swallowed: Vec::new(),
+            held: Vec::new(),
+            frame: Vec::new(),
+            dropped: false,
+            repeating: None,
+            scan: None,
+            matcher: Matcher::default(),
+            virtual_keyboard,
+        };
+        if device.virtual_keyboard.is_some() {
+            device.grab = GrabState::Pending;
+            device.try_grab();
+        }
+        self.devices.insert(token, device);
+        Ok(token)
+    }
+
+    fn remove_device(&mut self, token: u64) {
+        if let Some(device) = self.devices.remove(&token) {
+            if let Err(e) = self.epoll.delete(device.file.as_raw_fd()) {
+                trace!("failed to unregister {} - {}", device.path.display(), e);
+            }
+            if let Some(virtual_keyboard) = &device.virtual_keyboard {
+                if let Err(e) = self.epoll.delete(virtual_keyboard.as_raw_fd()) {
+                    trace!("failed to unregister {} - {}", VIRTUAL_KEYBOARD_NAME, e);
+                }
+            }
+            // keys held on the removed keyboard will never be released
+            if self.merge_key_states {
+                self.shared_key_state = KeyState::default();
+            }
+        }
+    }
+
+    // Token of the device whose virtual keyboard has the epoll token
+    fn led_owner(&self, token: u64) -> Option<u64> {
+        self.devices.iter().find_map(|(device_token, device)| {
+            let virtual_keyboard = device.virtual_keyboard.as_ref()?;
+            (virtual_keyboard.as_raw_fd() as u64 == token).then_some(*device_token)
+        })
+    }
+
+    fn forward_leds(&mut self, token: u64) {
+        let device = match self.devices.get_mut(&token) {
+            Some(device) => device,
+            None => return,
+        };
+        if let Some(virtual_keyboard) = &mut device.virtual_keyboard {
+            if let Err(e) = virtual_keyboard.forward_leds(&mut device.file) {
+                error!("failed to set LEDs of {} - {}", device.keyboard.name, e);
+            }
+        }
+    }
+
+    fn read_device(&mut self, token: u64) {
+        let mut buf = [0u8; SIZE_OF_INPUT_EVENT * EVENT_BATCH_SIZE];
+        let device = match self.devices.get_mut(&token) {
+            Some(device) => device,
+            None => return,
+        };
+        let num_of_bytes = match device.file.read(&mut buf) {
+            Ok(num_of_bytes) => num_of_bytes,
+            Err(e)
+                if e.kind() == io::ErrorKind::Interrupted
+                    || e.kind() == io::ErrorKind::WouldBlock =>
+            {
+                return
+            }
+            Err(e) => {
+                // ENODEV means the device was unplugged, there is nothing more to read
+                if e.raw_os_error() == Some(libc::ENODEV) {
+                    info!("{} was removed", device.keyboard.name);
+                } else {
+                    error!(
+                        "failed reading input event from {} - {}",
+                        device.keyboard.name, e
+                    );
+                }
+                self.remove_device(token);
+                return;
+            }
+        };
+        // the kernel only ever returns whole events
+        if num_of_bytes % SIZE_OF_INPUT_EVENT != 0 {
+            error!("invalid input {:?}", &buf[..num_of_bytes]);
+        }
+        for raw_event in buf[..num_of_bytes].chunks_exact(SIZE_OF_INPUT_EVENT) {
+            match InputEvent::new(raw_event) {
+                Ok(event) => self.queue_event(token, event),
+                Err(e) => error!("Error: failed parsing InputEvent - {}", e),
+            }
+        }
+    }
+
+    // Collects events into frames ending with SYN_REPORT, which describe the
+    // state of the device at a single point in time, and processes complete frames
+    fn queue_event(&mut self, token: u64, event: InputEvent) {
+        let device = match self.devices.get_mut(&token) {
+            Some(device) => device,
+            None => return,
+        };
+        match (event.type_, event.code) {
+            (SYN_EV, SYN_DROPPED) => {
+                info!(
+                    "{} dropped events, resynchronising pressed keys",
+                    device.keyboard.name
+                );
+                device.frame.clear();
+                device.dropped = true;
+            }
+            (SYN_EV, SYN_REPORT) if device.dropped => {
+                device.dropped = false;
+                match pressed_keys(&device.file) {
+                    Ok(pressed) => self.resync(token, &pressed),
+                    Err(e) => error!(
+                        "failed to read pressed keys of {} - {}",
+                        device.keyboard.name, e
+                    ),
+                }
+            }
+            (SYN_EV, SYN_REPORT) => {
+                let mut frame = mem::take(&mut device.frame);
+                frame.push(event);
+                for event in &frame {
+                    self.process_event(token, event);
+                }
+            }
+            _ if device.dropped => {}
+            _ => device.frame.push(event),
+        }
+    }
+
+    fn process_event(&mut self, token: u64, event: &InputEvent) {
+        if let Some(device) = self.devices.get_mut(&token) {
+            match (event.type_, event.code) {
+                (MSC_EV, MSC_SCAN) => device.scan = Some(event.value as u32),
+                (SYN_EV, _) => device.scan = None,
+                _ => {}
+            }
+        }
+        let bound = if event.is_key_event() && event.is_key_repeat() {
+            self.repeat(token, event);
+            false
+        } else {
+            match self.handle_event(token, event) {
+                Some(chord) => self.trigger(token, event, &chord),
+                None => false,
+            }
+        };
+        self.pass_through(token, event, bound);
+    }
+
+    // Runs the keybinding completed by the chord of the event, returns whether the
+    // chord is part of any keybinding
+    fn trigger(&mut self, token: u64, event: &InputEvent, chord: &Chord) -> bool {
+        trace!("Current chord: {:?}", chord);
+        // the mode and the matched keybinding have to come from the same config
+        let keybindings = self.keybindings.clone();
+        let config = match keybindings.lock() {
+            Ok(config) => config,
+            Err(e) => {
+                error!("faild to aquire lock for keybindings - {}", e);
+                return false;
+            }
+        };
+        self.leave_removed_mode(&config);
+        let device = match self.devices.get_mut(&token) {
+            Some(device) => device,
+            None => return false,
+        };
+        let matcher = if self.merge_key_states {
+            &mut self.shared_matcher
+        } else {
+            &mut device.matcher
+        };
+        // keybindings of device sections matching the keyboard come first
+        let layers = config.keybindings(&self.mode, &device.keyboard, &device.links);
+        let matched = matcher.feed(&layers, *chord, event.time(), &self.chains);
+        drop(config);
+        let action = match matched {
+            Match::Complete(action) => action,
+            Match::Partial | Match::Aborted => return true,
+            Match::None => return false,
+        };
+        if let Some(exec) = &action.exec {
+            self.launcher.run(exec);
+        }
+        if let (Some(device), Some(exec)) = (self.devices.get_mut(&token), action.exec) {
+            if !chord.release && action.repeat != Repeat::Never {
+                device.repeating = Some(Repeating {
+                    code: event.code,
+                    repeat: action.repeat,
+                    exec,
+                    last_run: event.time(),
+                });
+            }
+        }
+        if let Some(mode) = action.enter {
+            self.enter_mode(mode);
+        }
+        true
+    }
+
+    // Switches the active keybindings, chains started in the previous mode are abandoned
+    fn enter_mode(&mut self, mode: String) {
+        info!("Entering mode '{}'", mode);
+        self.mode = mode;
+        self.shared_matcher = Matcher::default();
+        for device in self.devices.values_mut() {
+            device.matcher = Matcher::default();
+        }
+        self.write_mode_file();
+    }
+
+    // Called after the config was swapped, so the mode file doesn't keep naming a
+    // mode that no longer exists
+    fn reloaded(&mut self) {
+        if let Some(Err(e)) = self.reloads.as_ref().map(Notifier::clear) {
+            error!("failed to read reload notification - {}", e);
+        }
+        let keybindings = self.keybindings.clone();
+        match keybindings.lock() {
+            Ok(config) => self.leave_removed_mode(&config),
+            Err(e) => error!("faild to aquire lock for keybindings - {}", e),
+        };
+    }
+
+    // Switches to the default mode if a reload removed the active one
+    fn leave_removed_mode(&mut self, config: &Config) {
+        if !config.has_mode(&self.mode) {
+            info!("Mode '{}' was removed from the config", self.mode);
+            self.enter_mode(DEFAULT_MODE.to_string());
+        }
+    }
+
+    fn write_mode_file(&self) {
+        if let Some(path) = &self.mode_file {
+            if let Err(e) = fs::write(path, format!("{}\n", self.mode)) {
+                error!("failed to write mode to {} - {}", path.display(), e);
+            }
+        }
+    }
+
+    // Runs the keybinding again on autorepeat of its key if its repeat policy allows it
+    fn repeat(&mut self, token: u64, event: &InputEvent) {
+        let repeating = match self.devices.get_mut(&token) {
+            Some(Device {
+                repeating: Some(repeating),
+                ..
+            }) if repeating.code == event.code => repeating,
+            _ => return,
+        };
+        if repeating.repeat.runs(repeating.last_run, event.time()) {
+            repeating.last_run = event.time();
+            self.launcher.run(&repeating.exec);
+        }
+    }
+
+    // Brings the key state in line with keys that are actually held after events
+    // were dropped. Changed keys are passed through as if they were just pressed
+    // or released, but don't trigger keybindings.
+    fn resync(&mut self, token: u64, pressed: &[u16]) {
+        let held = match self.devices.get(&token) {
+            Some(device) => device.held.clone(),
+            None => return,
+        };
+        let released = held.iter().filter(|code| !pressed.contains(code));
+        let events: Vec<InputEvent> = released
+            .map(|code| InputEvent::synthetic(KEY_EV, *code, KEY_RELEASE))
+            .chain(
+                pressed
+                    .iter()
+                    .filter(|code| !held.contains(code))
+                    .map(|code| InputEvent::synthetic(KEY_EV, *code, KEY_PRESS)),
+            )
+            .chain(std::iter::once(InputEvent::synthetic(
+                SYN_EV, SYN_REPORT, 0,
+            )))
+            .collect();
+        for event in &events {
+            trace!("Resynchronising {:?}", event);
+            self.handle_event(token, event);
+            self.pass_through(token, event, false);
+        }
+    }
+
+    // Re-emits events of grabbed devices through the virtual keyboard, `bound`
+    // tells whether the event completed a chord with a keybinding
+    fn pass_through(&mut self, token: u64, event: &InputEvent, bound: bool) {
+        let device = match self.devices.get_mut(&token) {
+            Some(device) => device,
+            None => return,
+        };
+        let virtual_keyboard = match &mut device.virtual_keyboard {
+            Some(virtual_keyboard) => virtual_keyboard,
+            None => return,
+        };
+        match device.grab {
+            GrabState::Grabbed if should_pass_through(&mut device.swallowed, event, bound) => {
+                if let Err(e) = virtual_keyboard.emit(event) {
+                    error!(
+                        "failed to pass through event of {} - {}",
+                        device.keyboard.name, e
+                    );
+                }
+            }
+            GrabState::Pending if event.type_ == SYN_EV => device.try_grab(),
+            _ => {}
+        }
+    }
+
+    // Updates the key state of the device and returns the chord completed by a key
+    // press or release
+    fn handle_event(&mut self, token: u64, event: &InputEvent) -> Option<Chord> {
+        if !event.is_key_event() {
+            return None;
+        }
+        let device = self.devices.get_mut(&token)?;
+        if event.is_key_press() && !device.held.contains(&event.code) {
+            device.held.push(event.code);
+        } else if event.is_key_release() {
+            device.held.retain(|code| *code != event.code);
+        }
+        // the kernel only repeats the key that was pressed last
+        if event.is_key_press() || event.is_key_release() {
+            device.repeating = None;
+        }
+        // the scancode names the key only if a keybinding uses it, the key code otherwise
+        let k = match device.scan.take() {
+            Some(scan) if binds_scancode(&self.keybindings, scan) => Key::Scan(scan),
+            _ => event.as_enum(),
+        };
+        let key_state = if self.merge_key_states {
+            &mut self.shared_key_state
+        } else {
+            &mut device.key_state
+        };
+        if event.is_key_press() {
+            trace!("Pressed {:?}, key_code: {}", k, event.code);
+            key_state.press(k)
+        } else if event.is_key_release() {
+            trace!("Released {:?}", k);
+            key_state.release(k)
+        } else {
+            None
+        }
+    }
+}
+
+// Whether a keybinding of any mode contains the key with the scancode
+fn open_device(path: &Path, write: bool) -> io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(write)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)
+}
+
+fn binds_scancode(keybindings: &Mutex<Config>, scan: u32) -> bool {
+    let config = match keybindings.lock() {
+        Ok(config) => config,
+        Err(_) => return false,
+    };
+    let bound = config
+        .all_keybindings()
+        .flat_map(|keybindings| keybindings.keys())
+        .flat_map(|chain| chain.chords())
+        .any(|chord| chord.key == Key::Scan(scan));
+    bound
+}
+
+// Decides whether an event of a grabbed device reaches other applications. The
+// press completing a bound chord is swallowed together with its repeats and release.
+fn should_pass_through(swallowed: &mut Vec<u16>, event: &InputEvent, bound: bool) -> bool {
+    if !event.is_key_event() {
+        return true;
+    }
+    if event.is_key_press() {
+        if bound {
+            swallowed.push(event.code);
+        }
+        return !bound;
+    }
+    match swallowed.iter().position(|code| *code == event.code) {
+        Some(i) => {
+            if event.is_key_release() {
+                swallowed.remove(i);
+            }
+            false
+        }
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::io::FromRawFd;
+
+    fn key_event(code: u16, value: i32) -> InputEvent {
+        InputEvent::synthetic(KEY_EV, code, value)
+    }
+
+    // A manager with two fake keyboards backed by pipes
+    fn manager(merge: bool) -> (DeviceManager, u64, u64) {
+        manager_with(merge, Config::default())
+    }
+
+    fn manager_with(merge: bool, keybindings: Config) -> (DeviceManager, u64, u64) {
+        let mut m = DeviceManager::new(Arc::new(Mutex::new(keybindings)))
+            .unwrap()
+            .merge_key_states(merge);
+        let mut tokens = Vec::new();
+        for i in 0..2 {
+            let mut fds = [0; 2];
+            assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+            unsafe { libc::close(fds[1]) };
+            let file = unsafe { File::from_raw_fd(fds[0]) };
+            let path = PathBuf::from(format!("/dev/input/event{}", i));
+            tokens.push(
+                m.add_device(&Keyboard::new("N: Name=\"keyboard\""), path, file)
+                    .unwrap(),
+            );
+        }
+        (m, tokens[0], tokens[1])
+    }
+
+    // super is 125, q is 16
+    #[test]
+    fn keeps_key_state_per_device() {
+        let (mut m, a, b) = manager(false);
+        assert_eq!(m.handle_event(a, &key_event(125, KEY_PRESS)), None);
+        assert_eq!(
+            m.handle_event(b, &key_event(16, KEY_PRESS))
+                .map(|c| c.to_string()),
+            Some("q".to_string())
+        );
+        assert_eq!(
+            m.handle_event(a, &key_event(16, KEY_PRESS))
+                .map(|c| c.to_string()),
+            Some("lsuper + q".to_string())
+        );
+    }
+
+    #[test]
+    fn merges_key_states_when_configured() {
+        let (mut m, a, b) = manager(true);
+        assert_eq!(m.handle_event(a, &key_event(125, KEY_PRESS)), None);
+        assert_eq!(
+            m.handle_event(b, &key_event(16, KEY_PRESS))
+                .map(|c| c.to_string()),
+            Some("lsuper + q".to_string())
+        );
+        m.handle_event(b, &key_event(16, KEY_RELEASE));
+
+        // a removed keyboard can't leave modifiers held
+        m.remove_device(a);
+        assert_eq!(
+            m.handle_event(b, &key_event(16, KEY_PRESS))
+                .map(|c| c.to_string()),
+            Some("q".to_string())
+        );
+    }
+
+    #[test]
+    fn swallows_only_bound_keys() {
+        let mut swallowed = Vec::new();
+        let syn = InputEvent::synthetic(SYN_EV, SYN_REPORT, 0);
+        // super + q is bound, super and everything else passes through
+        let events = vec![
+            (key_event(125, KEY_PRESS), false, true),
+            (syn, false, true),
+            (key_event(16, KEY_PRESS), true, false),
+            (key_event(16, KEY_REPEAT), false, false),
+            (key_event(16, KEY_RELEASE), false, false),
+            (key_event(125, KEY_RELEASE), false, true),
+            (key_event(16, KEY_PRESS), false, true),
+            (key_event(16, KEY_RELEASE), false, true),
+        ];
+        for (event, bound, expected) in events {
+            assert_eq!(
+                should_pass_through(&mut swallowed, &event, bound),
+                expected,
+                "{:?}",
+                event
+            );
+        }
+        assert!(swallowed.is_empty());
+    }
+
+    #[test]
+    fn processes_complete_frames() {
+        let (mut m, a, _) = manager(false);
+        let syn = || InputEvent::synthetic(SYN_EV, SYN_REPORT, 0);
+        m.queue_event(a, InputEvent::synthetic(MSC_EV, MSC_SCAN, 0xdb));
+        m.queue_event(a, key_event(125, KEY_PRESS));
+        assert!(m.devices[&a].key_state.modifiers().is_empty());
+        m.queue_event(a, syn());
+        assert_eq!(m.devices[&a].key_state.modifiers(), Modifiers::LSUPER);
+        assert!(m.devices[&a].frame.is_empty());
+
+        // super is released while events are dropped
+        m.queue_event(a, InputEvent::synthetic(SYN_EV, SYN_DROPPED, 0));
+        m.queue_event(a, key_event(125, KEY_RELEASE));
+        m.queue_event(a, key_event(30, KEY_PRESS));
+        assert!(m.devices[&a].frame.is_empty());
+        assert_eq!(m.devices[&a].key_state.modifiers(), Modifiers::LSUPER);
+
+        m.resync(a, &[30]);
+        assert!(m.devices[&a].key_state.modifiers().is_empty());
+        assert_eq!(m.devices[&a].held, vec![30]);
+        assert_eq!(
+            m.handle_event(a, &key_event(16, KEY_PRESS)),
+            None,
+            "a and q are held, no chord"
+        );
+    }
+
+    #[test]
+    fn repeats_keybindings_by_policy() {
+        let keybindings = Cfg::new("rkdrc")
+            .exec_mode(ExecMode::Direct)
+            .parse_str("XF86AudioRaiseVolume | repeat 100ms\n\ttrue\nXF86AudioMute\n\ttrue\n")
+            .unwrap();
+        let (mut m, a, _) = manager_with(false, keybindings);
+        let ms = Duration::from_millis;
+        let last_run = |m: &DeviceManager| m.devices[&a].repeating.as_ref().map(|r| r.last_run);
+
+        // XF86AudioRaiseVolume is 115
+        m.process_event(a, &key_event(115, KEY_PRESS).with_time(ms(1000)));
+        assert_eq!(last_run(&m), Some(ms(1000)));
+        for (time, expected) in &[(1060, 1000), (1090, 1000), (1120, 1120), (1150, 1120)] {
+            m.process_event(a, &key_event(115, KEY_REPEAT).with_time(ms(*time)));
+            assert_eq!(last_run(&m), Some(ms(*expected)));
+        }
+        m.process_event(a, &key_event(115, KEY_RELEASE).with_time(ms(1200)));
+        assert_eq!(last_run(&m), None);
+
+        // XF86AudioMute (113) doesn't repeat
+        m.process_event(a, &key_event(113, KEY_PRESS));
+        assert_eq!(last_run(&m), None);
+    }
+
+    #[test]
+    fn switches_modes() {
+        let modes = Cfg::new("rkdrc")
+            .exec_mode(ExecMode::Direct)
+            .parse_str("super + r | mode resize\nmode resize\nh\n\ttrue\nesc | mode default\n")
+            .unwrap();
+        let mode_file = std::env::temp_dir().join(format!("rkd-mode-{}", std::process::id()));
+        let (m, a, _) = manager_with(false, modes);
+        let mut m = m.mode_file(Some(mode_file.clone()));
+        let press = |m: &mut DeviceManager, code| {
+            m.process_event(a, &key_event(code, KEY_PRESS));
+            m.process_event(a, &key_event(code, KEY_RELEASE));
+        };
+        // h is 35, r is 19 and esc is 1
+        press(&mut m, 35);
+        assert_eq!(m.mode(), DEFAULT_MODE);
+        m.process_event(a, &key_event(125, KEY_PRESS));
+        press(&mut m, 19);
+        m.process_event(a, &key_event(125, KEY_RELEASE));
+        assert_eq!(m.mode(), "resize");
+        assert_eq!(fs::read_to_string(&mode_file).unwrap(), "resize\n");
+        press(&mut m, 35);
+        assert_eq!(m.mode(), "resize");
+        press(&mut m, 1);
+        assert_eq!(m.mode(), DEFAULT_MODE);
+        assert_eq!(fs::read_to_string(&mode_file).unwrap(), "default\n");
+        fs::remove_file(&mode_file).unwrap();
+    }
+
+    #[test]
+    fn leaves_removed_modes() {
+        let parse = |content: &str| {
+            Cfg::new("rkdrc")
+                .exec_mode(ExecMode::Direct)
+                .parse_str(content)
+                .unwrap()
+        };
+        let mode_file =
+            std::env::temp_dir().join(format!("rkd-removed-mode-{}", std::process::id()));
+        let config = parse("XF86AudioMute | mode resize\nmode resize\nh\n\ttrue\n");
+        let (m, a, _) = manager_with(false, config);
+        let mut m = m.mode_file(Some(mode_file.clone()));
+        let press = |m: &mut DeviceManager, code| {
+            m.process_event(a, &key_event(code, KEY_PRESS));
+            m.process_event(a, &key_event(code, KEY_RELEASE));
+        };
+        // XF86AudioMute is 113 and h is 35
+        press(&mut m, 113);
+        assert_eq!(m.mode(), "resize");
+        assert_eq!(fs::read_to_string(&mode_file).unwrap(), "resize\n");
+
+        // the reloaded config has no resize mode, the mode is left on the next chord
+        *m.keybindings.lock().unwrap() = parse("super + h\n\ttrue\n");
+        press(&mut m, 35);
+        assert_eq!(m.mode(), DEFAULT_MODE);
+        assert_eq!(fs::read_to_string(&mode_file).unwrap(), "default\n");
+
+        // or as soon as the reloader reports the swap
+        *m.keybindings.lock().unwrap() =
+            parse("XF86AudioMute | mode resize\nmode resize\nh\n\ttrue\n");
+        press(&mut m, 113);
+        assert_eq!(m.mode(), "resize");
+        let reloads = Notifier::new().unwrap();
+        let mut m = m.reloads(Some(reloads.clone()));
+        *m.keybindings.lock().unwrap() = parse("super + h\n\ttrue\n");
+        reloads.notify().unwrap();
+        m.reloaded();
+        assert_eq!(m.mode(), DEFAULT_MODE);
+        assert_eq!(fs::read_to_string(&mode_file).unwrap(), "default\n");
+        fs::remove_file(&mode_file).unwrap();
+    }
+
+    #[test]
+    fn scopes_keybindings_to_devices() {
+        let config = Cfg::new("rkdrc")
+            .exec_mode(ExecMode::Direct)
+            .parse_str("device name=Pad\na | mode pad\nmode pad\nesc | mode default\n")
+            .unwrap();
+        let (mut m, a, b) = manager_with(false, config);
+        m.devices.get_mut(&b).unwrap().keyboard.name = "Pad".to_string();
+        // a is 30
+        let a_key = key_event(30, KEY_PRESS);
+        let chord = m.handle_event(a, &a_key).unwrap();
+        assert!(!m.trigger(a, &a_key, &chord));
+        assert_eq!(m.mode(), DEFAULT_MODE);
+        let chord = m.handle_event(b, &a_key).unwrap();
+        assert!(m.trigger(b, &a_key, &chord));
+        assert_eq!(m.mode(), "pad");
+    }
+
+    #[test]
+    fn names_keys_by_bound_scancodes() {
+        let keybindings = Cfg::new("rkdrc")
+            .parse_str("super + scan:0xdb\n\ttrue\n")
+            .unwrap();
+        let (mut m, a, _) = manager_with(false, keybindings);
+        let scan = |scan| InputEvent::synthetic(MSC_EV, MSC_SCAN, scan);
+        let syn = || InputEvent::synthetic(SYN_EV, SYN_REPORT, 0);
+        m.process_event(a, &key_event(125, KEY_PRESS));
+        // 0xdb is bound, 0x1e isn't and the key keeps its code
+        m.process_event(a, &scan(0xdb));
+        assert_eq!(
+            m.handle_event(a, &key_event(20, KEY_PRESS)),
+            Chord::from_keys(&[Key::LSuper, Key::Scan(0xdb)])
+        );
+        m.process_event(a, &scan(0xdb));
+        m.handle_event(a, &key_event(20, KEY_RELEASE));
+        assert!(m.devices[&a].key_state.chord().is_none());
+        m.process_event(a, &scan(0x1e));
+        assert_eq!(
+            m.handle_event(a, &key_event(30, KEY_PRESS)),
+            Chord::from_keys(&[Key::LSuper, Key::A])
+        );
+        m.handle_event(a, &key_event(30, KEY_RELEASE));
+        // the scancode only applies to the key event of its frame
+        m.process_event(a, &scan(0xdb));
+        m.process_event(a, &syn());
+        assert_eq!(
+            m.handle_event(a, &key_event(16, KEY_PRESS)),
+            Chord::from_keys(&[Key::LSuper, Key::Q])
+        );
+    }
+
+    #[test]
+    fn triggers_release_chords() {
+        let (mut m, a, _) = manager(false);
+        let super_t = Chord::from_keys(&[Key::LSuper, Key::T]).unwrap();
+        m.handle_event(a, &key_event(125, KEY_PRESS));
+        assert_eq!(m.handle_event(a, &key_event(20, KEY_PRESS)), Some(super_t));
+        assert_eq!(m.handle_event(a, &key_event(125, KEY_RELEASE)), None);
+        assert_eq!(
+            m.handle_event(a, &key_event(20, KEY_RELEASE)),
+            Some(super_t.on_release())
+        );
+    }
+}
@@ -0,0 +1,317 @@
+//! Matching of pressed chords against keybindings that may consist of chains
+//! of chords - `super + w ; h`.
+use super::*;
+
+/// Settings of chains shared by all keybindings.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChainOptions {
+    // A started chain is abandoned if its next chord isn't pressed within the timeout
+    pub timeout: Option<Duration>,
+    // Pressing this key abandons a started chain
+    pub abort_key: Key,
+}
+impl Default for ChainOptions {
+    fn default() -> Self {
+        ChainOptions {
+            timeout: None,
+            abort_key: Key::Esc,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Match {
+    // The chord completes a keybinding, its action should run
+    Complete(Action),
+    // The chord starts or continues a chain or runs a keybinding once it's released
+    Partial,
+    // The chord abandoned a started chain
+    Aborted,
+    None,
+}
+
+/// Remembers chords of a chain that was started but not completed yet.
+#[derive(Debug, Default)]
+pub struct Matcher {
+    pending: Vec<Chord>,
+    // Time of the event that completed the last pending chord
+    last_chord: Duration,
+}
+impl Matcher {
+    // Matches a chord pressed or released at `time` (a timestamp of an input event).
+    // A keybinding of an earlier layer wins over the same one of a later layer.
+    pub fn feed(
+        &mut self,
+        layers: &[&Keybindings],
+        chord: Chord,
+        time: Duration,
+        options: &ChainOptions,
+    ) -> Match {
+        if !self.pending.is_empty() {
+            if let Some(timeout) = options.timeout {
+                if time.saturating_sub(self.last_chord) > timeout {
+                    info!("Chain '{}' timed out", Chain::new(self.pending.clone()));
+                    self.pending.clear();
+                }
+            }
+        }
+        if !self.pending.is_empty() && !chord.release && chord.key == options.abort_key {
+            info!("Chain '{}' aborted", Chain::new(self.pending.clone()));
+            self.pending.clear();
+            return Match::Aborted;
+        }
+
+        // a chord with `lctrl` is matched by keybindings with `lctrl` or `ctrl`
+        let candidates: Vec<Chain> = chord
+            .variants()
+            .into_iter()
+            .map(|variant| {
+                let mut chords = self.pending.clone();
+                chords.push(variant);
+                Chain::new(chords)
+            })
+            .collect();
+        let action = layers
+            .iter()
+            .find_map(|keybindings| candidates.iter().find_map(|c| keybindings.get(c)));
+        if let Some(action) = action {
+            self.pending.clear();
+            return Match::Complete(action.clone());
+        }
+        if let Some(candidate) = candidates.iter().find(|c| {
+            layers
+                .iter()
+                .flat_map(|keybindings| keybindings.keys())
+                .any(|chain| chain.continues(c))
+        }) {
+            trace!("Pending chain '{}'", candidate);
+            self.pending = candidate.chords().to_vec();
+            self.last_chord = time;
+            return Match::Partial;
+        }
+        if chord.release {
+            // releasing keys of a pending chain doesn't abandon it
+            return Match::None;
+        }
+        let on_release = |candidate: &Chain| {
+            let mut chords = candidate.chords().to_vec();
+            if let Some(last) = chords.last_mut() {
+                *last = last.on_release();
+            }
+            let chain = Chain::new(chords);
+            layers
+                .iter()
+                .any(|keybindings| keybindings.contains_key(&chain))
+        };
+        if candidates.iter().any(on_release) {
+            return Match::Partial;
+        }
+        if self.pending.is_empty() {
+            Match::None
+        } else {
+            // a chord that doesn't continue the chain may start a new one
+            info!("Chain '{}' abandoned", Chain::new(self.pending.clone()));
+            self.pending.clear();
+            self.feed(layers, chord, time, options)
+        }
+    }
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keybindings() -> Keybindings {
+        Cfg::new("rkdrc")
+            .exec_mode(ExecMode::Direct)
+            .parse_str(
+                "super + w ; {h,l}
+\techo {west,east}
+super + w ; super + x ; x
+\techo close
+super + q
+\techo quit
+ctrl + p
+\techo ctrl
+rctrl + p
+\techo rctrl
+@super + t
+\techo released",
+            )
+            .unwrap()
+            .modes
+            .remove(DEFAULT_MODE)
+            .unwrap()
+    }
+
+    fn chord(keys: &[Key]) -> Chord {
+        Chord::from_keys(keys).unwrap()
+    }
+
+    fn echo(word: &str) -> Match {
+        Match::Complete(Action {
+            exec: Some(Exec::with_mode(format!("echo {}", word), &ExecMode::Direct).unwrap()),
+            repeat: Repeat::Never,
+            enter: None,
+        })
+    }
+
+    #[test]
+    fn matches_chains() {
+        let kb = keybindings();
+        let options = ChainOptions::default();
+        let mut m = Matcher::default();
+        let t = Duration::from_millis;
+        let super_w = chord(&[Key::Super, Key::W]);
+
+        assert_eq!(
+            m.feed(&[&kb], chord(&[Key::Super, Key::Q]), t(0), &options),
+            echo("quit")
+        );
+        assert_eq!(m.feed(&[&kb], super_w, t(0), &options), Match::Partial);
+        assert!(m.is_pending());
+        // releasing keys of the chain keeps it pending
+        assert_eq!(
+            m.feed(&[&kb], super_w.on_release(), t(10), &options),
+            Match::None
+        );
+        assert_eq!(
+            m.feed(&[&kb], chord(&[Key::L]), t(20), &options),
+            echo("east")
+        );
+        assert!(!m.is_pending());
+
+        m.feed(&[&kb], super_w, t(30), &options);
+        assert_eq!(
+            m.feed(&[&kb], chord(&[Key::Super, Key::X]), t(40), &options),
+            Match::Partial
+        );
+        assert_eq!(
+            m.feed(&[&kb], chord(&[Key::X]), t(50), &options),
+            echo("close")
+        );
+
+        // a chord that doesn't continue the chain starts over
+        m.feed(&[&kb], super_w, t(60), &options);
+        assert_eq!(
+            m.feed(&[&kb], chord(&[Key::J]), t(70), &options),
+            Match::None
+        );
+        assert!(!m.is_pending());
+        m.feed(&[&kb], super_w, t(80), &options);
+        assert_eq!(
+            m.feed(&[&kb], chord(&[Key::Super, Key::Q]), t(90), &options),
+            echo("quit")
+        );
+    }
+
+    #[test]
+    fn prefers_modifier_sides() {
+        let kb = keybindings();
+        let options = ChainOptions::default();
+        let mut m = Matcher::default();
+        let t = Duration::default();
+        let p = |modifier| Chord::new(modifier, Key::P);
+        assert_eq!(
+            m.feed(&[&kb], p(Modifiers::RCTRL), t, &options),
+            echo("rctrl")
+        );
+        assert_eq!(
+            m.feed(&[&kb], p(Modifiers::LCTRL), t, &options),
+            echo("ctrl")
+        );
+        assert_eq!(
+            m.feed(&[&kb], p(Modifiers::LCTRL | Modifiers::RCTRL), t, &options),
+            echo("ctrl")
+        );
+        assert_eq!(m.feed(&[&kb], p(Modifiers::LALT), t, &options), Match::None);
+    }
+
+    #[test]
+    fn aborts_chains() {
+        let kb = keybindings();
+        let options = ChainOptions {
+            timeout: Some(Duration::from_secs(1)),
+            abort_key: Key::Esc,
+        };
+        let mut m = Matcher::default();
+        let t = Duration::from_millis;
+        let super_w = chord(&[Key::Super, Key::W]);
+
+        m.feed(&[&kb], super_w, t(0), &options);
+        assert_eq!(
+            m.feed(&[&kb], chord(&[Key::Esc]), t(10), &options),
+            Match::Aborted
+        );
+        assert_eq!(
+            m.feed(&[&kb], chord(&[Key::H]), t(20), &options),
+            Match::None
+        );
+
+        m.feed(&[&kb], super_w, t(100), &options);
+        assert_eq!(
+            m.feed(&[&kb], chord(&[Key::H]), t(1200), &options),
+            Match::None
+        );
+        m.feed(&[&kb], super_w, t(2000), &options);
+        assert_eq!(
+            m.feed(&[&kb], chord(&[Key::H]), t(2900), &options),
+            echo("west")
+        );
+    }
+
+    #[test]
+    fn matches_release_chords() {
+        let kb = keybindings();
+        let options = ChainOptions::default();
+        let mut m = Matcher::default();
+        let super_t = chord(&[Key::Super, Key::T]);
+        assert_eq!(
+            m.feed(&[&kb], super_t, Duration::default(), &options),
+            Match::Partial
+        );
+        assert!(!m.is_pending());
+        assert_eq!(
+            m.feed(&[&kb], super_t.on_release(), Duration::default(), &options),
+            echo("released")
+        );
+    }
+
+    #[test]
+    fn prefers_earlier_layers() {
+        let kb = keybindings();
+        let pad = Cfg::new("rkdrc")
+            .exec_mode(ExecMode::Direct)
+            .parse_str("device name=Pad\nsuper + q\n\techo pad\nsuper + w ; a\n\techo a")
+            .unwrap()
+            .scopes
+            .remove(0)
+            .modes
+            .remove(DEFAULT_MODE)
+            .unwrap();
+        let options = ChainOptions::default();
+        let mut m = Matcher::default();
+        let t = Duration::from_millis;
+        let super_q = chord(&[Key::Super, Key::Q]);
+        assert_eq!(m.feed(&[&pad, &kb], super_q, t(0), &options), echo("pad"));
+        assert_eq!(m.feed(&[&kb, &pad], super_q, t(0), &options), echo("quit"));
+        // chains may continue with keybindings of any layer
+        let super_w = chord(&[Key::Super, Key::W]);
+        assert_eq!(
+            m.feed(&[&pad, &kb], super_w, t(10), &options),
+            Match::Partial
+        );
+        assert_eq!(
+            m.feed(&[&pad, &kb], chord(&[Key::H]), t(20), &options),
+            echo("west")
+        );
+        m.feed(&[&pad, &kb], super_w, t(30), &options);
+        assert_eq!(
+            m.feed(&[&pad, &kb], chord(&[Key::A]), t(40), &options),
+            echo("a")
+        );
+    }
+}
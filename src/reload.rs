@@ -0,0 +1,300 @@
+//! Reloading of keybindings while rkd is running, either after receiving
+//! SIGUSR1 or after the config file was modified.
+use super::*;
+use std::sync::mpsc::{self, Sender};
+
+// Editors tend to emit a couple of events when saving a file, all events
+// received within this period cause only a single reload.
+const RELOAD_DEBOUNCE: Duration = Duration::from_millis(100);
+const INOTIFY_EVENT_MASK: u32 = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_CREATE;
+
+#[derive(Debug)]
+enum ReloadEvent {
+    Signal,
+    FileChanged,
+}
+
+// Starts threads reloading the config on SIGUSR1 and, if `watch` is set, when the
+// config file changes. `reloads` is notified whenever the keybindings were swapped. Has to be called before any other thread is spawned so that
+// SIGUSR1 is blocked in all of them.
+pub fn spawn_reloader<P>(
+    cfg: Cfg<P>,
+    keybindings: Arc<Mutex<Config>>,
+    watch: bool,
+    reloads: Notifier,
+) -> io::Result<()>
+where
+    P: AsRef<Path> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel();
+    let sigset = block_sigusr1()?;
+    let _tx = tx.clone();
+    thread::spawn(move || wait_for_signal(sigset, _tx));
+
+    if watch {
+        let path = cfg.file().to_path_buf();
+        let inotify = watch_file(&path)?;
+        info!("Watching {} for changes", path.display());
+        thread::spawn(move || read_file_events(inotify, &path, tx));
+    }
+
+    thread::spawn(move || {
+        while let Ok(event) = rx.recv() {
+            thread::sleep(RELOAD_DEBOUNCE);
+            while rx.try_recv().is_ok() {}
+            info!("Reloading config - {:?}", event);
+            if reload(&cfg, &keybindings) {
+                if let Err(e) = reloads.notify() {
+                    error!("failed to notify about the reload - {}", e);
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+// Parses the config and swaps the keybindings if it's valid, returns whether
+// the keybindings were swapped.
+pub fn reload<P: AsRef<Path>>(cfg: &Cfg<P>, keybindings: &Arc<Mutex<Config>>) -> bool {
+    match cfg.parse() {
+        Ok(new) => match keybindings.lock() {
+            Ok(mut current) => {
+                for change in diff_config(&current, &new) {
+                    info!("{}", change);
+                }
+                *current = new;
+                true
+            }
+            Err(e) => {
+                error!("faild to aquire lock for keybindings - {}", e);
+                false
+            }
+        },
+        Err(errors) => {
+            for e in errors {
+                error!("{}", e);
+            }
+            error!("Config is invalid, keeping current keybindings");
+            false
+        }
+    }
+}
+
+// Describes keybindings that were added, removed or changed
+pub fn diff_keybindings(old: &Keybindings, new: &Keybindings) -> Vec<String> {
+    let mut changes = Vec::new();
+    for (chord, action) in new {
+        match old.get(chord) {
+            None => changes.push(format!("added '{}' - {:?}", chord, action.exec)),
+            Some(old_action) if old_action != action => {
+                changes.push(format!("changed '{}' - {:?}", chord, action.exec))
+            }
+            _ => {}
+        }
+    }
+    for chord in old.keys().filter(|chord| !new.contains_key(chord)) {
+        changes.push(format!("removed '{}'", chord));
+    }
+    changes.sort();
+    changes
+}
+
+// Describes changes of keybindings of every mode
+pub fn diff_modes(old: &Modes, new: &Modes) -> Vec<String> {
+    let empty = Keybindings::new();
+    let mut names: Vec<&String> = old.keys().chain(new.keys()).collect();
+    names.sort();
+    names.dedup();
+    let mut changes = Vec::new();
+    for name in names {
+        let diff = diff_keybindings(
+            old.get(name).unwrap_or(&empty),
+            new.get(name).unwrap_or(&empty),
+        );
+        if name == DEFAULT_MODE {
+            changes.extend(diff);
+        } else {
+            changes.extend(
+                diff.into_iter()
+                    .map(|c| format!("{} in mode '{}'", c, name)),
+            );
+        }
+    }
+    changes
+}
+
+// Describes changes of keybindings of every mode and device section
+pub fn diff_config(old: &Config, new: &Config) -> Vec<String> {
+    let empty = Modes::new();
+    let mut filters: Vec<&DeviceFilter> = Vec::new();
+    for scope in old.scopes.iter().chain(&new.scopes) {
+        if !filters.contains(&&scope.filter) {
+            filters.push(&scope.filter);
+        }
+    }
+    let mut changes = diff_modes(&old.modes, &new.modes);
+    for filter in filters {
+        let diff = diff_modes(
+            old.scoped_modes(filter).unwrap_or(&empty),
+            new.scoped_modes(filter).unwrap_or(&empty),
+        );
+        changes.extend(
+            diff.into_iter()
+                .map(|c| format!("{} on device '{}'", c, filter)),
+        );
+    }
+    changes
+}
+
+fn block_sigusr1() -> io::Result<libc::sigset_t> {
+    unsafe {
+        let mut sigset: libc::sigset_t = mem::zeroed();
+        libc::sigemptyset(&mut sigset);
+        libc::sigaddset(&mut sigset, libc::SIGUSR1);
+        match libc::pthread_sigmask(libc::SIG_BLOCK, &sigset, std::ptr::null_mut()) {
+            0 => Ok(sigset),
+            e => Err(io::Error::from_raw_os_error(e)),
+        }
+    }
+}
+
+fn wait_for_signal(sigset: libc::sigset_t, tx: Sender<ReloadEvent>) {
+    loop {
+        let mut signal = 0;
+        match unsafe { libc::sigwait(&sigset, &mut signal) } {
+            0 => {
+                if tx.send(ReloadEvent::Signal).is_err() {
+                    return;
+                }
+            }
+            e => error!(
+                "failed waiting for a signal - {}",
+                io::Error::from_raw_os_error(e)
+            ),
+        }
+    }
+}
+
+// Watches the directory of the file as most editors replace the file on save
+fn watch_file(path: &Path) -> io::Result<Inotify> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let inotify = Inotify::new()?;
+    inotify.add_watch(dir, INOTIFY_EVENT_MASK)?;
+    Ok(inotify)
+}
+
+fn read_file_events(mut inotify: Inotify, path: &Path, tx: Sender<ReloadEvent>) {
+    let file_name = match path.file_name() {
+        Some(name) => name.to_os_string(),
+        None => return,
+    };
+    loop {
+        let names = match inotify.read_names() {
+            Ok(names) => names,
+            Err(e) => {
+                error!("failed reading inotify events - {}", e);
+                return;
+            }
+        };
+        if names.contains(&file_name) && tx.send(ReloadEvent::FileChanged).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use self::Key::*;
+    use super::*;
+
+    fn keybindings(v: &[(&[Key], &str)]) -> Keybindings {
+        v.iter()
+            .map(|(keys, cmd)| {
+                let action = Action {
+                    exec: Some(Exec::new(cmd).unwrap()),
+                    repeat: Repeat::Never,
+                    enter: None,
+                };
+                (Chord::from_keys(keys).unwrap().into(), action)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn diffs_keybindings() {
+        let old = keybindings(&[
+            (&[Super, Q], "bspc node -c"),
+            (&[Super, Return], "termite"),
+            (&[Super, D], "rofi"),
+        ]);
+        let new = keybindings(&[
+            (&[Super, Q], "bspc node -c"),
+            (&[Super, Return], "alacritty"),
+            (&[Super, Shift, D], "rofi"),
+        ]);
+        let changes = diff_keybindings(&old, &new);
+        assert_eq!(changes.len(), 3);
+        assert!(changes[0].starts_with("added 'super + shift + d'"));
+        assert!(changes[1].starts_with("changed 'super + return'"));
+        assert_eq!(changes[2], "removed 'super + d'");
+        assert!(diff_keybindings(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn diffs_modes() {
+        let mut old = Modes::new();
+        old.insert(
+            DEFAULT_MODE.to_string(),
+            keybindings(&[(&[Super, Q], "bspc node -c")]),
+        );
+        old.insert(
+            "resize".to_string(),
+            keybindings(&[(&[H], "bspc node -z left")]),
+        );
+        let mut new = old.clone();
+        new.remove("resize");
+        new.insert("launcher".to_string(), keybindings(&[(&[F], "firefox")]));
+        let changes = diff_modes(&old, &new);
+        assert_eq!(changes.len(), 2);
+        assert!(changes[0].starts_with("added 'f'"));
+        assert!(changes[0].ends_with("in mode 'launcher'"));
+        assert_eq!(changes[1], "removed 'h' in mode 'resize'");
+    }
+
+    #[test]
+    fn diffs_device_sections() {
+        let cfg = Cfg::new("rkdrc");
+        let old = cfg
+            .parse_str("super + q\n\tbspc node -c\ndevice name=Pad\na\n\tfirefox\n")
+            .unwrap();
+        let new = cfg
+            .parse_str("super + q\n\tbspc node -c\ndevice name=Pad\nb\n\tfirefox\n")
+            .unwrap();
+        let changes = diff_config(&old, &new);
+        assert_eq!(changes.len(), 2);
+        assert!(changes[0].starts_with("added 'b'"));
+        assert!(changes[0].ends_with("on device 'name=\"Pad\"'"));
+        assert_eq!(changes[1], "removed 'a' on device 'name=\"Pad\"'");
+        assert!(diff_config(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn swaps_only_valid_configs() {
+        let path = std::env::temp_dir().join(format!("rkd-reload-{}", std::process::id()));
+        let cfg = Cfg::new(path.clone());
+        fs::write(&path, "super + q\n\tbspc node -c\n").unwrap();
+        let kb = Arc::new(Mutex::new(cfg.parse().unwrap()));
+
+        fs::write(&path, "super + q\n\tbspc node -k\nsuper + w\n\trofi\n").unwrap();
+        assert!(reload(&cfg, &kb));
+        assert_eq!(kb.lock().unwrap().modes[DEFAULT_MODE].len(), 2);
+
+        fs::write(&path, "supr + q\n\tbspc node -c\n").unwrap();
+        assert!(!reload(&cfg, &kb));
+        assert_eq!(kb.lock().unwrap().modes[DEFAULT_MODE].len(), 2);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,107 @@
+//! How keybindings react to autorepeat of their key while it's held down.
+use super::*;
+use std::fmt;
+
+/// Repeat policy of a keybinding, written after a `|` following the keys of
+/// the keybinding - `XF86AudioRaiseVolume | repeat 100ms`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Repeat {
+    /// Run the command once when the key is pressed
+    #[default]
+    Never,
+    /// Run the command on every autorepeat event of the key
+    Always,
+    /// Run the command on autorepeat events at most once per interval
+    Every(Duration),
+}
+impl Repeat {
+    // Parses `norepeat`, `repeat` or `repeat <duration>`
+    pub fn parse(option: &str) -> Result<Repeat, String> {
+        let words: Vec<&str> = option.split_whitespace().collect();
+        match words.as_slice() {
+            ["norepeat"] => Ok(Repeat::Never),
+            ["repeat"] => Ok(Repeat::Always),
+            ["repeat", interval] => match parse_duration(interval) {
+                Some(interval) if interval > Duration::default() => Ok(Repeat::Every(interval)),
+                _ => Err(format!(
+                    "invalid interval '{}' - expected a duration like 100ms or 1s",
+                    interval
+                )),
+            },
+            _ => Err(format!(
+                "unknown option '{}' - expected 'repeat', 'repeat <interval>' or 'norepeat'",
+                option.trim()
+            )),
+        }
+    }
+    // Decides whether an autorepeat event received at `time` runs the command,
+    // which last ran at `last_run`. Both are timestamps of input events.
+    pub fn runs(self, last_run: Duration, time: Duration) -> bool {
+        match self {
+            Repeat::Never => false,
+            Repeat::Always => true,
+            Repeat::Every(interval) => time.saturating_sub(last_run) >= interval,
+        }
+    }
+}
+impl fmt::Display for Repeat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Repeat::Never => f.write_str("norepeat"),
+            Repeat::Always => f.write_str("repeat"),
+            Repeat::Every(interval) => write!(f, "repeat {}ms", interval.as_millis()),
+        }
+    }
+}
+
+// Parses durations like `150ms`, `2s` or `0.5s`
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    if let Some(ms) = s.strip_suffix("ms") {
+        return ms.parse().ok().map(Duration::from_millis);
+    }
+    let secs: f64 = s.strip_suffix('s')?.parse().ok()?;
+    // Rejects negative, infinite and too large values
+    Duration::try_from_secs_f64(secs).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_repeat_options() {
+        assert_eq!(Repeat::parse("repeat"), Ok(Repeat::Always));
+        assert_eq!(Repeat::parse(" norepeat "), Ok(Repeat::Never));
+        assert_eq!(
+            Repeat::parse("repeat 150ms"),
+            Ok(Repeat::Every(Duration::from_millis(150)))
+        );
+        assert_eq!(
+            Repeat::parse("repeat 0.5s"),
+            Ok(Repeat::Every(Duration::from_millis(500)))
+        );
+        assert!(Repeat::parse("repeat 0ms").is_err());
+        assert!(Repeat::parse("repeat fast").is_err());
+        assert!(Repeat::parse("repeat 1e20s").is_err());
+        assert!(Repeat::parse("repeat -1s").is_err());
+        assert_eq!(parse_duration("1e20s"), None);
+        assert_eq!(parse_duration("infs"), None);
+        assert!(Repeat::parse("repaet").is_err());
+        assert_eq!(
+            Repeat::Every(Duration::from_millis(150)).to_string(),
+            "repeat 150ms"
+        );
+    }
+
+    #[test]
+    fn rate_limits_repeats() {
+        let ms = Duration::from_millis;
+        assert!(!Repeat::Never.runs(ms(0), ms(1000)));
+        assert!(Repeat::Always.runs(ms(0), ms(30)));
+        let every = Repeat::Every(ms(100));
+        assert!(!every.runs(ms(500), ms(560)));
+        assert!(every.runs(ms(500), ms(600)));
+        // timestamps going backwards don't cause a burst
+        assert!(!every.runs(ms(500), ms(400)));
+    }
+}
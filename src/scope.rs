@@ -0,0 +1,163 @@
+//! Device sections of a config file. Keybindings following a `device` line apply
+//! only to keyboards matching it, while keybindings outside of any section apply
+//! to all of them.
+use super::*;
+use std::fmt;
+
+/// Properties a keyboard is matched by, all of the given ones have to match.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct DeviceFilter {
+    pub name: Option<String>,
+    // Vendor and product ID
+    pub id: Option<(u16, u16)>,
+    pub phys: Option<String>,
+    // Name of a link in /dev/input/by-id
+    pub by_id: Option<String>,
+}
+impl DeviceFilter {
+    // Parses `name="Macro Pad" id=1d50:615e phys=usb-0000:00:14.0-3/input0 by-id=usb-Pad-event-kbd`
+    pub fn parse(args: &str) -> Result<DeviceFilter, String> {
+        let mut filter = DeviceFilter::default();
+        for arg in split_args(args)? {
+            let (name, value) = arg
+                .split_once('=')
+                .ok_or_else(|| format!("expected name=value, found '{}'", arg))?;
+            let value = value.trim_matches('"').to_string();
+            match name {
+                "name" => filter.name = Some(value),
+                "id" => filter.id = Some(parse_id(&value)?),
+                "phys" => filter.phys = Some(value),
+                "by-id" => filter.by_id = Some(value),
+                _ => return Err(format!("unknown property '{}'", name)),
+            }
+        }
+        if filter == DeviceFilter::default() {
+            return Err("expected at least one of name, id, phys or by-id".to_string());
+        }
+        Ok(filter)
+    }
+    // `links` are names of /dev/input/by-id links of the keyboard's event file
+    pub fn matches(&self, keyboard: &Keyboard, links: &[String]) -> bool {
+        self.name.as_ref().is_none_or(|name| *name == keyboard.name)
+            && self
+                .id
+                .is_none_or(|id| id == (keyboard.id.vendor, keyboard.id.product))
+            && self.phys.as_ref().is_none_or(|phys| *phys == keyboard.phys)
+            && self.by_id.as_ref().is_none_or(|link| links.contains(link))
+    }
+}
+impl fmt::Display for DeviceFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut args = Vec::new();
+        if let Some(name) = &self.name {
+            args.push(format!("name=\"{}\"", name));
+        }
+        if let Some((vendor, product)) = self.id {
+            args.push(format!("id={:04x}:{:04x}", vendor, product));
+        }
+        if let Some(phys) = &self.phys {
+            args.push(format!("phys={}", phys));
+        }
+        if let Some(link) = &self.by_id {
+            args.push(format!("by-id={}", link));
+        }
+        f.write_str(&args.join(" "))
+    }
+}
+
+/// Keybindings of all `device` sections with the same filter.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scope {
+    pub filter: DeviceFilter,
+    pub modes: Modes,
+}
+
+// Splits arguments at whitespace outside of double quotes
+fn split_args(args: &str) -> Result<Vec<&str>, String> {
+    let mut split = Vec::new();
+    let mut start = None;
+    let mut quoted = false;
+    for (i, ch) in args.char_indices() {
+        match ch {
+            '"' => quoted = !quoted,
+            _ if ch.is_whitespace() && !quoted => {
+                if let Some(start) = start.take() {
+                    split.push(&args[start..i]);
+                }
+                continue;
+            }
+            _ => {}
+        }
+        start.get_or_insert(i);
+    }
+    if quoted {
+        return Err("unterminated quote".to_string());
+    }
+    split.extend(start.map(|start| &args[start..]));
+    Ok(split)
+}
+
+// Parses a `vendor:product` pair of hexadecimal IDs as shown by `lsusb`
+fn parse_id(id: &str) -> Result<(u16, u16), String> {
+    let invalid = || {
+        format!(
+            "invalid id '{}' - expected vendor:product like 046d:c33a",
+            id
+        )
+    };
+    let (vendor, product) = id.split_once(':').ok_or_else(invalid)?;
+    match (
+        u16::from_str_radix(vendor, 16),
+        u16::from_str_radix(product, 16),
+    ) {
+        (Ok(vendor), Ok(product)) => Ok((vendor, product)),
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_devices() {
+        let pad = Keyboard::new(
+            "I: Bus=0003 Vendor=1d50 Product=615e Version=0111
+N: Name=\"Work Louder Macro Pad\"
+P: Phys=usb-0000:00:14.0-3/input0",
+        );
+        let links = vec!["usb-Work_Louder_Macro_Pad-event-kbd".to_string()];
+        let filter = DeviceFilter::parse("name=\"Work Louder Macro Pad\" id=1d50:615E").unwrap();
+        assert_eq!(filter.id, Some((0x1d50, 0x615e)));
+        assert!(filter.matches(&pad, &links));
+        assert_eq!(
+            filter.to_string(),
+            "name=\"Work Louder Macro Pad\" id=1d50:615e"
+        );
+
+        let filter = DeviceFilter::parse("phys=usb-0000:00:14.0-3/input0").unwrap();
+        assert!(filter.matches(&pad, &links));
+        let filter = DeviceFilter::parse("by-id=usb-Work_Louder_Macro_Pad-event-kbd").unwrap();
+        assert!(filter.matches(&pad, &links));
+        assert!(!filter.matches(&pad, &[]));
+        let filter = DeviceFilter::parse("name=\"Work Louder Macro Pad\" id=046d:c33a").unwrap();
+        assert!(!filter.matches(&pad, &links));
+    }
+
+    #[test]
+    fn rejects_invalid_filters() {
+        let errors = vec![
+            ("", "expected at least one of name, id, phys or by-id"),
+            ("name=\"Macro Pad", "unterminated quote"),
+            ("vendor=1d50", "unknown property 'vendor'"),
+            ("Macro", "expected name=value, found 'Macro'"),
+            (
+                "id=1d50",
+                "invalid id '1d50' - expected vendor:product like 046d:c33a",
+            ),
+        ];
+        for (args, e) in errors {
+            assert_eq!(DeviceFilter::parse(args), Err(e.to_string()), "{}", args);
+        }
+    }
+}
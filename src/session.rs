@@ -0,0 +1,226 @@
+//! Environment of the graphical session commands are started in. rkd is often
+//! started at boot before any session exists, so the variables pointing to the
+//! session are read again when commands are executed.
+use super::*;
+use std::sync::mpsc::{self, Sender};
+use std::time::Instant;
+
+const SYSTEMCTL: &str = "systemctl";
+const XDG_RUNTIME_DIR: &str = "XDG_RUNTIME_DIR";
+// How long read variables are reused, so a held key repeating its command doesn't
+// read them on every repeat
+const SESSION_ENV_TTL: Duration = Duration::from_secs(1);
+pub const SESSION_VARIABLES: &[&str] = &[
+    "WAYLAND_DISPLAY",
+    "DISPLAY",
+    XDG_RUNTIME_DIR,
+    "DBUS_SESSION_BUS_ADDRESS",
+];
+
+/// Where the variables of SESSION_VARIABLES are imported from.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum SessionEnv {
+    /// Keep the environment rkd was started with
+    #[default]
+    Inherit,
+    /// Read `NAME=value` lines from a file, like the output of `env` or a
+    /// file written by the session's startup script
+    File(PathBuf),
+    /// Use the environment of the systemd user manager
+    Systemd,
+}
+impl SessionEnv {
+    // Parses the value of the `--session-env` option
+    pub fn from_arg(arg: &str) -> SessionEnv {
+        match arg {
+            "systemd" => SessionEnv::Systemd,
+            path => SessionEnv::File(PathBuf::from(path)),
+        }
+    }
+    // Reads the current session variables, ones that are missing aren't returned
+    pub fn variables(&self) -> io::Result<Vec<(String, String)>> {
+        match self {
+            SessionEnv::Inherit => Ok(Vec::new()),
+            SessionEnv::File(path) => Ok(parse_env(&fs::read_to_string(path)?)),
+            SessionEnv::Systemd => {
+                let mut cmd = Command::new(SYSTEMCTL);
+                cmd.args(["--user", "show-environment"]);
+                // systemctl finds the user manager through the runtime directory.
+                // root has no user manager of its own, so its uid isn't guessed.
+                let uid = unsafe { libc::getuid() };
+                if std::env::var_os(XDG_RUNTIME_DIR).is_none() && uid != 0 {
+                    cmd.env(XDG_RUNTIME_DIR, format!("/run/user/{}", uid));
+                }
+                let output = cmd.output()?;
+                if !output.status.success() {
+                    return Err(io::Error::other(format!(
+                        "{} --user show-environment failed - {}",
+                        SYSTEMCTL,
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    )));
+                }
+                Ok(parse_env(&String::from_utf8_lossy(&output.stdout)))
+            }
+        }
+    }
+}
+
+/// Runs commands with the session environment. Reading it may block, e.g. on
+/// `systemctl`, so unless the environment is inherited commands are started from
+/// a separate thread and input handling never waits for it.
+#[derive(Debug, Default)]
+pub struct Launcher {
+    sender: Option<Sender<Exec>>,
+}
+impl Launcher {
+    pub fn new(session_env: SessionEnv) -> Launcher {
+        if session_env == SessionEnv::Inherit {
+            return Launcher::default();
+        }
+        let (sender, receiver) = mpsc::channel::<Exec>();
+        thread::spawn(move || {
+            let mut cache = SessionCache::new(session_env);
+            // commands are started in the order they were triggered
+            for exec in receiver {
+                run_exec(&exec, cache.variables());
+            }
+        });
+        Launcher {
+            sender: Some(sender),
+        }
+    }
+    pub fn run(&self, exec: &Exec) {
+        match &self.sender {
+            Some(sender) => {
+                if sender.send(exec.clone()).is_err() {
+                    error!("failed to execute command - session environment thread exited");
+                }
+            }
+            None => run_exec(exec, &[]),
+        }
+    }
+}
+
+// Session variables read at most once per SESSION_ENV_TTL. When they can't be
+// read the last known ones are used.
+struct SessionCache {
+    session_env: SessionEnv,
+    variables: Vec<(String, String)>,
+    read_at: Option<Instant>,
+}
+impl SessionCache {
+    fn new(session_env: SessionEnv) -> SessionCache {
+        SessionCache {
+            session_env,
+            variables: Vec::new(),
+            read_at: None,
+        }
+    }
+    fn variables(&mut self) -> &[(String, String)] {
+        let now = Instant::now();
+        if self
+            .read_at
+            .is_none_or(|read_at| now.duration_since(read_at) >= SESSION_ENV_TTL)
+        {
+            match self.session_env.variables() {
+                Ok(variables) => self.variables = variables,
+                Err(e) => error!("failed to read session environment - {}", e),
+            }
+            self.read_at = Some(now);
+        }
+        &self.variables
+    }
+}
+
+// Extracts session variables from `NAME=value` lines. Lines may start with
+// `export`, values may be quoted and systemd quotes special characters with `$'...'`.
+pub fn parse_env(content: &str) -> Vec<(String, String)> {
+    let mut variables: Vec<(String, String)> = Vec::new();
+    for line in content.lines().map(str::trim) {
+        if line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line).trim_start();
+        let (name, value) = match line.split_once('=') {
+            Some((name, value)) if SESSION_VARIABLES.contains(&name) => (name, value),
+            _ => continue,
+        };
+        let value = unquote(value.strip_prefix('$').unwrap_or(value));
+        variables.retain(|(n, _)| n != name);
+        variables.push((name.to_string(), value.to_string()));
+    }
+    variables
+}
+
+fn unquote(value: &str) -> &str {
+    for quote in &['\'', '"'] {
+        if value.len() >= 2 && value.starts_with(*quote) && value.ends_with(*quote) {
+            return &value[1..value.len() - 1];
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(v: &[(&str, &str)]) -> Vec<(String, String)> {
+        v.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_session_variables() {
+        let content = "# written by ~/.config/sway/config
+export WAYLAND_DISPLAY=wayland-1
+XDG_RUNTIME_DIR=\"/run/user/1000\"
+HOME=/home/user
+DISPLAY=:0
+DISPLAY=':1'
+DBUS_SESSION_BUS_ADDRESS=$'unix:path=/run/user/1000/bus'
+";
+        assert_eq!(
+            parse_env(content),
+            vars(&[
+                ("WAYLAND_DISPLAY", "wayland-1"),
+                ("XDG_RUNTIME_DIR", "/run/user/1000"),
+                ("DISPLAY", ":1"),
+                ("DBUS_SESSION_BUS_ADDRESS", "unix:path=/run/user/1000/bus"),
+            ])
+        );
+        assert!(parse_env("WAYLAND_DISPLAYX=1\n=2\n").is_empty());
+    }
+
+    #[test]
+    fn reads_variables_from_file() {
+        let path = std::env::temp_dir().join(format!("rkd-session-{}", std::process::id()));
+        fs::write(&path, "WAYLAND_DISPLAY=wayland-0\n").unwrap();
+        let session = SessionEnv::from_arg(path.to_str().unwrap());
+        assert_eq!(
+            session.variables().unwrap(),
+            vars(&[("WAYLAND_DISPLAY", "wayland-0")])
+        );
+        fs::remove_file(&path).unwrap();
+        assert!(session.variables().is_err());
+        assert!(SessionEnv::Inherit.variables().unwrap().is_empty());
+        assert_eq!(SessionEnv::from_arg("systemd"), SessionEnv::Systemd);
+    }
+
+    #[test]
+    fn caches_variables() {
+        let path = std::env::temp_dir().join(format!("rkd-cache-{}", std::process::id()));
+        fs::write(&path, "DISPLAY=:0\n").unwrap();
+        let mut cache = SessionCache::new(SessionEnv::File(path.clone()));
+        assert_eq!(cache.variables(), vars(&[("DISPLAY", ":0")]).as_slice());
+        fs::write(&path, "DISPLAY=:1\n").unwrap();
+        assert_eq!(cache.variables(), vars(&[("DISPLAY", ":0")]).as_slice());
+        cache.read_at = cache.read_at.map(|read_at| read_at - SESSION_ENV_TTL);
+        assert_eq!(cache.variables(), vars(&[("DISPLAY", ":1")]).as_slice());
+        // the last known variables are kept when the file disappears
+        fs::remove_file(&path).unwrap();
+        cache.read_at = cache.read_at.map(|read_at| read_at - SESSION_ENV_TTL);
+        assert_eq!(cache.variables(), vars(&[("DISPLAY", ":1")]).as_slice());
+    }
+}
@@ -0,0 +1,245 @@
+//! Virtual keyboards created through uinput, used to pass through events of
+//! grabbed keyboards that don't belong to any keybinding. Each grabbed keyboard
+//! gets its own copy reporting the same kinds of events, and LED changes made
+//! on the copy, like caps lock, are forwarded to the real keyboard.
+use super::*;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+const UINPUT: &str = "/dev/uinput";
+const UINPUT_MAX_NAME_SIZE: usize = 80;
+const ABS_CNT: usize = 64;
+const BUS_VIRTUAL: u16 = 0x06;
+// Highest codes of event types besides keys, see linux/input-event-codes.h
+const REL_MAX: u16 = 0x0f;
+const ABS_MAX: u16 = 0x3f;
+const MSC_MAX: u16 = 0x07;
+const SW_MAX: u16 = 0x11;
+const LED_MAX: u16 = 0x0f;
+
+// struct uinput_user_dev from linux/uinput.h
+#[repr(C)]
+struct UinputUserDev {
+    name: [u8; UINPUT_MAX_NAME_SIZE],
+    id: libc::input_id,
+    ff_effects_max: u32,
+    absmax: [i32; ABS_CNT],
+    absmin: [i32; ABS_CNT],
+    absfuzz: [i32; ABS_CNT],
+    absflat: [i32; ABS_CNT],
+}
+
+// struct input_absinfo from linux/input.h
+#[repr(C)]
+#[derive(Default)]
+struct AbsInfo {
+    value: i32,
+    minimum: i32,
+    maximum: i32,
+    fuzz: i32,
+    flat: i32,
+    resolution: i32,
+}
+
+#[derive(Debug)]
+pub struct VirtualKeyboard {
+    file: File,
+}
+impl VirtualKeyboard {
+    // Creates a copy of the keyboard whose event file is `device`
+    pub fn new(keyboard: &Keyboard, device: &File) -> io::Result<VirtualKeyboard> {
+        let file = open_uinput()?;
+        for (request, code) in capability_requests(keyboard) {
+            ioctl(&file, request, code)?;
+        }
+
+        let mut dev: UinputUserDev = unsafe { mem::zeroed() };
+        dev.name[..VIRTUAL_KEYBOARD_NAME.len()].copy_from_slice(VIRTUAL_KEYBOARD_NAME.as_bytes());
+        dev.id.bustype = BUS_VIRTUAL;
+        dev.id.version = 1;
+        // axes like the volume knob of some keyboards need their range
+        for abs in (0..=ABS_MAX).filter(|abs| keyboard.abs.has(*abs)) {
+            let info = abs_info(device, abs).unwrap_or_default();
+            let i = abs as usize;
+            dev.absmin[i] = info.minimum;
+            dev.absmax[i] = info.maximum;
+            dev.absfuzz[i] = info.fuzz;
+            dev.absflat[i] = info.flat;
+        }
+        let raw = unsafe {
+            std::slice::from_raw_parts(
+                &dev as *const UinputUserDev as *const u8,
+                mem::size_of::<UinputUserDev>(),
+            )
+        };
+        (&file).write_all(raw)?;
+        if unsafe { libc::ioctl(file.as_raw_fd(), UI_DEV_CREATE as _) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(VirtualKeyboard { file })
+    }
+    // Emits an event as if it was read from this keyboard, the kernel sets a new timestamp
+    pub fn emit(&mut self, event: &InputEvent) -> io::Result<()> {
+        self.file.write_all(&event.to_bytes())
+    }
+    // Writes LED changes other applications made on this keyboard to the real one
+    pub fn forward_leds(&mut self, device: &mut File) -> io::Result<()> {
+        forward_leds(&mut self.file, device)
+    }
+}
+impl AsRawFd for VirtualKeyboard {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+impl Drop for VirtualKeyboard {
+    fn drop(&mut self) {
+        unsafe { libc::ioctl(self.file.as_raw_fd(), UI_DEV_DESTROY as _) };
+    }
+}
+
+// Checks that virtual keyboards can be created before any keyboard is grabbed
+pub fn check_uinput() -> io::Result<()> {
+    open_uinput().map(drop)
+}
+
+fn open_uinput() -> io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(UINPUT)
+}
+
+// ioctls declaring every event type and code the keyboard reports. Autorepeat
+// isn't declared, repeats of the real keyboard are passed through instead.
+fn capability_requests(keyboard: &Keyboard) -> Vec<(u32, u16)> {
+    let types = [
+        (KEY_EV, UI_SET_KEYBIT, &keyboard.key, KEY_MAX),
+        (REL_EV, UI_SET_RELBIT, &keyboard.rel, REL_MAX),
+        (ABS_EV, UI_SET_ABSBIT, &keyboard.abs, ABS_MAX),
+        (MSC_EV, UI_SET_MSCBIT, &keyboard.msc, MSC_MAX),
+        (SW_EV, UI_SET_SWBIT, &keyboard.sw, SW_MAX),
+        (LED_EV, UI_SET_LEDBIT, &keyboard.led, LED_MAX),
+    ];
+    let mut requests = Vec::new();
+    for (ev, request, codes, max) in types {
+        if keyboard.ev.has(ev) {
+            requests.push((UI_SET_EVBIT, ev));
+            requests.extend(
+                (0..=max)
+                    .filter(|code| codes.has(*code))
+                    .map(|code| (request, code)),
+            );
+        }
+    }
+    requests
+}
+
+// Copies LED events read from the virtual keyboard to the real one, which applies
+// them once they're followed by SYN_REPORT. Other events, like force feedback
+// requests, are dropped.
+fn forward_leds<R: Read, W: Write>(virtual_keyboard: &mut R, device: &mut W) -> io::Result<()> {
+    let mut buf = [0u8; SIZE_OF_INPUT_EVENT * 16];
+    loop {
+        let n = match virtual_keyboard.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let mut leds = Vec::new();
+        for raw_event in buf[..n].chunks_exact(SIZE_OF_INPUT_EVENT) {
+            let event = InputEvent::new(raw_event)?;
+            if event.type_ == LED_EV {
+                leds.extend(event.to_bytes());
+            }
+        }
+        if !leds.is_empty() {
+            leds.extend(InputEvent::synthetic(SYN_EV, SYN_REPORT, 0).to_bytes());
+            device.write_all(&leds)?;
+        }
+    }
+}
+
+fn abs_info(device: &File, abs: u16) -> io::Result<AbsInfo> {
+    let mut info = AbsInfo::default();
+    let request = EVIOCGABS + u32::from(abs);
+    if unsafe { libc::ioctl(device.as_raw_fd(), request as _, &mut info as *mut AbsInfo) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(info)
+}
+
+fn ioctl(file: &File, request: u32, arg: u16) -> io::Result<()> {
+    if unsafe { libc::ioctl(file.as_raw_fd(), request as _, arg as libc::c_int) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(fixture: &str, name: &str) -> Keyboard {
+        parse_input_devices(fixture)
+            .into_iter()
+            .find(|k| k.name == name)
+            .unwrap()
+    }
+
+    fn codes(requests: &[(u32, u16)], request: u32) -> Vec<u16> {
+        requests
+            .iter()
+            .filter(|(r, _)| *r == request)
+            .map(|(_, code)| *code)
+            .collect()
+    }
+
+    #[test]
+    fn copies_capabilities() {
+        let desktop = include_str!("../fixtures/devices_desktop");
+        // EV=12001f, a wheel and a volume knob besides keys and LEDs
+        let requests = capability_requests(&device(desktop, "Keychron K2"));
+        assert_eq!(
+            codes(&requests, UI_SET_EVBIT),
+            vec![KEY_EV, REL_EV, ABS_EV, MSC_EV, LED_EV]
+        );
+        assert_eq!(codes(&requests, UI_SET_RELBIT), vec![0x06, 0x0c]);
+        assert_eq!(codes(&requests, UI_SET_ABSBIT), vec![0x20]);
+        assert_eq!(codes(&requests, UI_SET_MSCBIT), vec![MSC_SCAN]);
+        let keys = codes(&requests, UI_SET_KEYBIT);
+        assert!(keys.contains(&1) && keys.contains(&30));
+        assert!(keys.contains(&0x110), "mouse buttons are copied too");
+        assert_eq!(codes(&requests, UI_SET_LEDBIT), vec![0, 1, 2, 3, 4]);
+
+        let laptop = include_str!("../fixtures/devices_laptop");
+        let requests = capability_requests(&device(laptop, "ThinkPad Extra Buttons"));
+        assert_eq!(codes(&requests, UI_SET_EVBIT), vec![KEY_EV, MSC_EV, SW_EV]);
+        assert_eq!(codes(&requests, UI_SET_SWBIT), vec![3]);
+    }
+
+    #[test]
+    fn forwards_leds() {
+        let events: Vec<u8> = [
+            InputEvent::synthetic(LED_EV, 1, 1),
+            InputEvent::synthetic(SYN_EV, SYN_REPORT, 0),
+            // EV_UINPUT force feedback request
+            InputEvent::synthetic(0x0101, 1, 0),
+        ]
+        .iter()
+        .flat_map(InputEvent::to_bytes)
+        .collect();
+        let mut device = Vec::new();
+        forward_leds(&mut Cursor::new(events), &mut device).unwrap();
+        let written: Vec<(u16, u16, i32)> = device
+            .chunks_exact(SIZE_OF_INPUT_EVENT)
+            .map(|raw| InputEvent::new(raw).unwrap())
+            .map(|e| (e.type_, e.code, e.value))
+            .collect();
+        assert_eq!(written, vec![(LED_EV, 1, 1), (SYN_EV, SYN_REPORT, 0)]);
+    }
+}
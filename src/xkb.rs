@@ -0,0 +1,357 @@
+//! Optional layout layer - keysyms written in the config are resolved to the keys
+//! producing them in an XKB keymap compiled by libxkbcommon. The library is loaded
+//! at runtime so rkd works without it as long as no layout is configured.
+use super::*;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+
+const LIBXKBCOMMON: &str = "libxkbcommon.so.0";
+pub const DEFAULT_KEYBOARD: &str = "/etc/default/keyboard";
+// XKB key codes are evdev key codes shifted by 8
+const EVDEV_OFFSET: u32 = 8;
+const XKB_MOD_INVALID: u32 = 0xffff_ffff;
+// Modifier that selects the third level, AltGr on most layouts
+const LEVEL_THREE_MOD: &str = "Mod5";
+
+/// Names of the XKB rules the keymap is compiled from, empty ones fall back to
+/// the defaults of libxkbcommon.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LayoutNames {
+    pub model: String,
+    pub layout: String,
+    pub variant: String,
+    pub options: String,
+}
+impl LayoutNames {
+    // Parses `layout=de variant=nodeadkeys options=caps:escape`
+    pub fn parse(args: &str) -> Result<LayoutNames, String> {
+        let mut names = LayoutNames::default();
+        for arg in args.split_whitespace() {
+            let (name, value) = arg
+                .split_once('=')
+                .ok_or_else(|| format!("expected name=value, found '{}'", arg))?;
+            match name {
+                "model" => names.model = value.to_string(),
+                "layout" => names.layout = value.to_string(),
+                "variant" => names.variant = value.to_string(),
+                "options" => names.options = value.to_string(),
+                _ => return Err(format!("unknown setting '{}'", name)),
+            }
+        }
+        Ok(names)
+    }
+    // Reads XKBMODEL, XKBLAYOUT, XKBVARIANT and XKBOPTIONS of a file like
+    // /etc/default/keyboard
+    pub fn from_default_keyboard(content: &str) -> LayoutNames {
+        let mut names = LayoutNames::default();
+        for line in content.lines() {
+            let (name, value) = match line.trim().split_once('=') {
+                Some((name, value)) => (name, value.trim_matches('"').to_string()),
+                None => continue,
+            };
+            match name {
+                "XKBMODEL" => names.model = value,
+                "XKBLAYOUT" => names.layout = value,
+                "XKBVARIANT" => names.variant = value,
+                "XKBOPTIONS" => names.options = value,
+                _ => {}
+            }
+        }
+        names
+    }
+}
+
+// A keysym along with the keys that type it
+#[derive(Clone, Debug, PartialEq)]
+struct Symbol {
+    name: String,
+    ch: Option<char>,
+    keys: Vec<Key>,
+}
+
+/// Keysyms of the first layout of a compiled keymap. Each keysym is typed by the
+/// key on the lowest level that produces it, along with shift or AltGr if the
+/// level needs them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keymap {
+    symbols: Vec<Symbol>,
+}
+impl Keymap {
+    pub fn new(names: &LayoutNames) -> io::Result<Keymap> {
+        let xkb = XkbCommon::load()?;
+        xkb.compile(names)
+    }
+    // Keys typing the character or the keysym with the given name - `eacute`, `at`.
+    // Names that differ only in case are told apart if possible.
+    pub fn keys(&self, token: &str) -> Option<Vec<Key>> {
+        let mut chars = token.chars();
+        let symbol = match (chars.next(), chars.next()) {
+            (Some(ch), None) => self.symbols.iter().find(|s| s.ch == Some(ch)),
+            _ => self.symbols.iter().find(|s| s.name == token).or_else(|| {
+                self.symbols
+                    .iter()
+                    .find(|s| s.name.eq_ignore_ascii_case(token))
+            }),
+        };
+        symbol.map(|s| s.keys.clone())
+    }
+}
+
+// A library opened with dlopen, closed when dropped
+struct Library(*mut c_void);
+impl Library {
+    fn open(name: &str) -> io::Result<Library> {
+        let name =
+            CString::new(name).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let handle = unsafe { libc::dlopen(name.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+        if handle.is_null() {
+            return Err(dl_error());
+        }
+        Ok(Library(handle))
+    }
+    // The caller has to make sure that T is a function pointer of the right signature
+    unsafe fn symbol<T: Copy>(&self, name: &str) -> io::Result<T> {
+        let name =
+            CString::new(name).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let symbol = libc::dlsym(self.0, name.as_ptr());
+        if symbol.is_null() {
+            return Err(dl_error());
+        }
+        Ok(mem::transmute_copy(&symbol))
+    }
+}
+impl Drop for Library {
+    fn drop(&mut self) {
+        unsafe { libc::dlclose(self.0) };
+    }
+}
+
+fn dl_error() -> io::Error {
+    let message = unsafe {
+        let e = libc::dlerror();
+        if e.is_null() {
+            "unknown error".to_string()
+        } else {
+            CStr::from_ptr(e).to_string_lossy().into_owned()
+        }
+    };
+    io::Error::new(io::ErrorKind::NotFound, message)
+}
+
+#[repr(C)]
+struct RuleNames {
+    rules: *const c_char,
+    model: *const c_char,
+    layout: *const c_char,
+    variant: *const c_char,
+    options: *const c_char,
+}
+
+// Functions of libxkbcommon used to read a keymap
+struct XkbCommon {
+    _lib: Library,
+    context_new: unsafe extern "C" fn(c_int) -> *mut c_void,
+    context_unref: unsafe extern "C" fn(*mut c_void),
+    keymap_new_from_names:
+        unsafe extern "C" fn(*mut c_void, *const RuleNames, c_int) -> *mut c_void,
+    keymap_unref: unsafe extern "C" fn(*mut c_void),
+    min_keycode: unsafe extern "C" fn(*mut c_void) -> u32,
+    max_keycode: unsafe extern "C" fn(*mut c_void) -> u32,
+    num_levels_for_key: unsafe extern "C" fn(*mut c_void, u32, u32) -> u32,
+    key_get_syms_by_level:
+        unsafe extern "C" fn(*mut c_void, u32, u32, u32, *mut *const u32) -> c_int,
+    key_get_mods_for_level:
+        unsafe extern "C" fn(*mut c_void, u32, u32, u32, *mut u32, usize) -> usize,
+    mod_get_index: unsafe extern "C" fn(*mut c_void, *const c_char) -> u32,
+    keysym_get_name: unsafe extern "C" fn(u32, *mut c_char, usize) -> c_int,
+    keysym_to_utf32: unsafe extern "C" fn(u32) -> u32,
+}
+impl XkbCommon {
+    fn load() -> io::Result<XkbCommon> {
+        let lib = Library::open(LIBXKBCOMMON)?;
+        unsafe {
+            Ok(XkbCommon {
+                context_new: lib.symbol("xkb_context_new")?,
+                context_unref: lib.symbol("xkb_context_unref")?,
+                keymap_new_from_names: lib.symbol("xkb_keymap_new_from_names")?,
+                keymap_unref: lib.symbol("xkb_keymap_unref")?,
+                min_keycode: lib.symbol("xkb_keymap_min_keycode")?,
+                max_keycode: lib.symbol("xkb_keymap_max_keycode")?,
+                num_levels_for_key: lib.symbol("xkb_keymap_num_levels_for_key")?,
+                key_get_syms_by_level: lib.symbol("xkb_keymap_key_get_syms_by_level")?,
+                key_get_mods_for_level: lib.symbol("xkb_keymap_key_get_mods_for_level")?,
+                mod_get_index: lib.symbol("xkb_keymap_mod_get_index")?,
+                keysym_get_name: lib.symbol("xkb_keysym_get_name")?,
+                keysym_to_utf32: lib.symbol("xkb_keysym_to_utf32")?,
+                _lib: lib,
+            })
+        }
+    }
+
+    fn compile(&self, names: &LayoutNames) -> io::Result<Keymap> {
+        let to_c =
+            |s: &str| CString::new(s).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e));
+        let (model, layout) = (to_c(&names.model)?, to_c(&names.layout)?);
+        let (variant, options) = (to_c(&names.variant)?, to_c(&names.options)?);
+        // empty names are passed as null so that libxkbcommon uses its defaults
+        let or_null = |s: &CString| {
+            if s.as_bytes().is_empty() {
+                ptr::null()
+            } else {
+                s.as_ptr()
+            }
+        };
+        let rule_names = RuleNames {
+            rules: ptr::null(),
+            model: or_null(&model),
+            layout: or_null(&layout),
+            variant: or_null(&variant),
+            options: or_null(&options),
+        };
+        unsafe {
+            let context = (self.context_new)(0);
+            if context.is_null() {
+                return Err(io::Error::other("failed to create an XKB context"));
+            }
+            let keymap = (self.keymap_new_from_names)(context, &rule_names, 0);
+            let result = if keymap.is_null() {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("failed to compile an XKB keymap from {:?}", names),
+                ))
+            } else {
+                let symbols = self.symbols(keymap);
+                (self.keymap_unref)(keymap);
+                Ok(Keymap { symbols })
+            };
+            (self.context_unref)(context);
+            result
+        }
+    }
+
+    // Lists keysyms of the first layout, lower levels first
+    unsafe fn symbols(&self, keymap: *mut c_void) -> Vec<Symbol> {
+        let mod_index = |name: &str| {
+            let name = CString::new(name).unwrap();
+            match (self.mod_get_index)(keymap, name.as_ptr()) {
+                XKB_MOD_INVALID => 0,
+                index => 1 << index,
+            }
+        };
+        let (shift, level_three) = (mod_index("Shift"), mod_index(LEVEL_THREE_MOD));
+        let keycodes = (self.min_keycode)(keymap)..=(self.max_keycode)(keymap);
+        let max_level = keycodes
+            .clone()
+            .map(|keycode| (self.num_levels_for_key)(keymap, keycode, 0))
+            .max()
+            .unwrap_or(0);
+
+        let mut symbols: Vec<Symbol> = Vec::new();
+        for level in 0..max_level {
+            for keycode in keycodes.clone() {
+                let key = Key::from_code(keycode.saturating_sub(EVDEV_OFFSET) as u16);
+                if key == Key::UK || level >= (self.num_levels_for_key)(keymap, keycode, 0) {
+                    continue;
+                }
+                // the first combination of modifiers selecting the level
+                let mut mask = 0;
+                if (self.key_get_mods_for_level)(keymap, keycode, 0, level, &mut mask, 1) == 0 {
+                    continue;
+                }
+                // levels reached with other modifiers, like keypad keys with num lock
+                if mask & !(shift | level_three) != 0 {
+                    continue;
+                }
+                let mut keys = Vec::new();
+                if mask & shift != 0 {
+                    keys.push(Key::Shift);
+                }
+                if mask & level_three != 0 {
+                    keys.push(Key::RAlt);
+                }
+                keys.push(key);
+
+                let mut syms = ptr::null();
+                let count = (self.key_get_syms_by_level)(keymap, keycode, 0, level, &mut syms);
+                if count != 1 {
+                    continue;
+                }
+                let sym = *syms;
+                let mut buf = [0 as c_char; 64];
+                if (self.keysym_get_name)(sym, buf.as_mut_ptr(), buf.len()) <= 0 {
+                    continue;
+                }
+                let name = CStr::from_ptr(buf.as_ptr()).to_string_lossy().into_owned();
+                if symbols.iter().any(|s| s.name == name) {
+                    continue;
+                }
+                let ch = std::char::from_u32((self.keysym_to_utf32)(sym)).filter(|ch| *ch != '\0');
+                symbols.push(Symbol { name, ch, keys });
+            }
+        }
+        symbols
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_layout_names() {
+        let names = LayoutNames::parse("layout=de variant=nodeadkeys options=caps:escape").unwrap();
+        assert_eq!(names.layout, "de");
+        assert_eq!(names.variant, "nodeadkeys");
+        assert_eq!(names.options, "caps:escape");
+        assert!(LayoutNames::parse("layout de").is_err());
+        assert!(LayoutNames::parse("keys=de").is_err());
+
+        let names = LayoutNames::from_default_keyboard(
+            "# KEYBOARD CONFIGURATION FILE
+XKBMODEL=\"pc105\"
+XKBLAYOUT=\"fr\"
+XKBVARIANT=\"\"
+XKBOPTIONS=\"compose:ralt\"
+
+BACKSPACE=\"guess\"",
+        );
+        assert_eq!(
+            names,
+            LayoutNames {
+                model: "pc105".to_string(),
+                layout: "fr".to_string(),
+                variant: String::new(),
+                options: "compose:ralt".to_string(),
+            }
+        );
+    }
+
+    fn keymap(layout: &str) -> Keymap {
+        let names = LayoutNames {
+            layout: layout.to_string(),
+            ..Default::default()
+        };
+        Keymap::new(&names).unwrap_or_else(|e| panic!("{} is not usable - {}", LIBXKBCOMMON, e))
+    }
+
+    #[test]
+    #[ignore = "needs libxkbcommon and xkeyboard-config, run with --ignored"]
+    fn resolves_keysyms() {
+        let (us, fr, de) = (keymap("us"), keymap("fr"), keymap("de"));
+        use self::Key::*;
+        assert_eq!(us.keys("a"), Some(vec![A]));
+        assert_eq!(us.keys("@"), Some(vec![Shift, Num2]));
+        assert_eq!(us.keys("A"), Some(vec![Shift, A]));
+        // a and q are swapped on AZERTY, digits need shift
+        assert_eq!(fr.keys("a"), Some(vec![Q]));
+        assert_eq!(fr.keys("1"), Some(vec![Shift, Num1]));
+        assert_eq!(fr.keys("eacute"), Some(vec![Num2]));
+        assert_eq!(fr.keys("@"), Some(vec![RAlt, Num0]));
+        assert_eq!(de.keys("z"), Some(vec![Y]));
+        assert_eq!(de.keys("ssharp"), Some(vec![Dash]));
+        assert_eq!(de.keys("Adiaeresis"), Some(vec![Shift, Apostrophe]));
+        assert_eq!(de.keys("adiaeresis"), Some(vec![Apostrophe]));
+        assert_eq!(de.keys("nosuchkeysym"), None);
+    }
+}